@@ -0,0 +1,374 @@
+// [[file:../gchemol-core.note::7c4f6714][7c4f6714]]
+//! RMSD between molecules without known atom correspondence.
+// 7c4f6714 ends here
+
+// [[file:../gchemol-core.note::cb42d83e][cb42d83e]]
+use crate::common::*;
+use crate::{Molecule, Point3};
+
+use gchemol_geometry::prelude::*;
+use gchemol_geometry::Superimpose;
+use gchemol_graph::petgraph::algo;
+use vecfx::{Matrix3f, Vector3f};
+// cb42d83e ends here
+
+// [[file:../gchemol-core.note::f72bee2b][f72bee2b]]
+/// Options for RMSD calculation with automatic atom mapping.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RmsdOptions {
+    /// Allow the structure to be mirrored for better alignment. Enantiomers
+    /// will be treated as identical if enabled.
+    pub allow_reflection: bool,
+
+    /// Try atom mappings from graph isomorphisms based on chemical bonds
+    /// first.
+    pub use_bonds: bool,
+
+    /// The maximum number of graph isomorphisms to be evaluated.
+    pub max_isomorphisms: usize,
+
+    /// The maximum number of iterations for assignment refinement.
+    pub max_iterations: usize,
+}
+
+impl Default for RmsdOptions {
+    fn default() -> Self {
+        Self {
+            allow_reflection: false,
+            use_bonds: true,
+            max_isomorphisms: 1000,
+            max_iterations: 50,
+        }
+    }
+}
+
+/// The result of alignment with automatic atom mapping.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RmsdMapping {
+    /// The superposition RMSD weighted by atomic masses.
+    pub rmsd: f64,
+
+    /// Atom mapping in pairs of serial numbers: (atom in the molecule, atom
+    /// in the reference)
+    pub mapping: Vec<(usize, usize)>,
+
+    /// True if the molecule was mirrored for alignment.
+    pub reflected: bool,
+
+    /// Aligned positions of atoms in the molecule, ordered by serial numbers.
+    pub positions: Vec<Point3>,
+}
+// f72bee2b ends here
+
+// [[file:../gchemol-core.note::691f1f9e][691f1f9e]]
+/// Solve the linear assignment problem for square `cost` matrix with
+/// Hungarian algorithm. Return the column assigned to each row.
+fn hungarian(cost: &[Vec<f64>]) -> Vec<usize> {
+    let n = cost.len();
+    // potentials and matching using 1-based index, with 0 as the dummy
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; n + 1];
+    let mut p = vec![0; n + 1];
+    let mut way = vec![0; n + 1];
+    for i in 1..=n {
+        p[0] = i;
+        let mut j0 = 0;
+        let mut minv = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[j0] = true;
+            let i0 = p[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=n {
+                if !used[j] {
+                    let cur = cost[i0 - 1][j - 1] - u[i0] - v[j];
+                    if cur < minv[j] {
+                        minv[j] = cur;
+                        way[j] = j0;
+                    }
+                    if minv[j] < delta {
+                        delta = minv[j];
+                        j1 = j;
+                    }
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[p[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    minv[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if p[j0] == 0 {
+                break;
+            }
+        }
+        loop {
+            let j1 = way[j0];
+            p[j0] = p[j1];
+            j0 = j1;
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut assigned = vec![0; n];
+    for j in 1..=n {
+        assigned[p[j] - 1] = j - 1;
+    }
+    assigned
+}
+
+/// Centered positions with element types for alignment.
+struct Structure {
+    positions: Vec<Point3>,
+    numbers: Vec<usize>,
+    weights: Vec<f64>,
+}
+
+impl Structure {
+    fn from_molecule(mol: &Molecule) -> Self {
+        let com = mol.center_of_mass();
+        Self {
+            positions: mol.positions().map(|p| [p[0] - com[0], p[1] - com[1], p[2] - com[2]]).collect(),
+            numbers: mol.atomic_numbers().collect(),
+            weights: mol.masses().collect(),
+        }
+    }
+
+    /// Return positions mirrored for handedness `reflected`.
+    fn oriented_positions(&self, reflected: bool) -> Vec<Point3> {
+        let mut positions = self.positions.clone();
+        if reflected {
+            positions.mirror_invert();
+        }
+        positions
+    }
+}
+
+/// Superimpose `positions` onto `reference` with `mapping` from the
+/// indices of `positions` to the indices of `reference`. Return the rmsd
+/// and the aligned positions.
+fn superimpose_mapped(positions: &[Point3], reference: &[Point3], weights: &[f64], mapping: &[usize]) -> (f64, Vec<Point3>) {
+    let mut ordered_ref = vec![[0.0; 3]; reference.len()];
+    for (i, &j) in mapping.iter().enumerate() {
+        ordered_ref[i] = reference[j];
+    }
+    let sp = Superimpose::new(positions).onto(&ordered_ref, weights.into());
+    (sp.rmsd, sp.apply(positions))
+}
+
+/// Assign atoms in `positions` to the nearest atoms of the same element in
+/// `reference`.
+fn assign_atoms(positions: &[Point3], this: &Structure, reference: &Structure) -> Vec<usize> {
+    let mut mapping = vec![0; positions.len()];
+    for n in this.numbers.iter().unique() {
+        let rows = (0..positions.len()).filter(|&i| this.numbers[i] == *n).collect_vec();
+        let cols = (0..reference.positions.len()).filter(|&j| reference.numbers[j] == *n).collect_vec();
+        let cost = rows
+            .iter()
+            .map(|&i| cols.iter().map(|&j| positions[i].distance(reference.positions[j]).powi(2)).collect())
+            .collect_vec();
+        for (i, c) in hungarian(&cost).into_iter().enumerate() {
+            mapping[rows[i]] = cols[c];
+        }
+    }
+    mapping
+}
+
+/// Return the principal axes in rows as a proper rotation matrix.
+fn principal_frame(positions: &[Point3], weights: &[f64]) -> Matrix3f {
+    let mut m = Matrix3f::zeros();
+    for (p, w) in positions.iter().zip(weights) {
+        let p = Vector3f::from(*p);
+        m += *w * (Matrix3f::identity() * p.norm_squared() - p * p.transpose());
+    }
+    let eigen = m.symmetric_eigen();
+    let mut frame = eigen.eigenvectors.transpose();
+    if frame.determinant() < 0.0 {
+        frame.row_mut(2).neg_mut();
+    }
+    frame
+}
+
+/// The 24 proper rotations of a cube for sampling initial orientations.
+fn cube_rotations() -> Vec<Matrix3f> {
+    let mut rotations = vec![];
+    for perm in (0..3).permutations(3) {
+        for signs in (0..3).map(|_| [1.0, -1.0]).multi_cartesian_product() {
+            let mut m = Matrix3f::zeros();
+            for i in 0..3 {
+                m[(i, perm[i])] = signs[i];
+            }
+            if m.determinant() > 0.0 {
+                rotations.push(m);
+            }
+        }
+    }
+    rotations
+}
+
+/// Search the atom mapping by iterative assignment and superposition,
+/// starting from orientations aligned by principal axes.
+fn align_by_assignment(this: &Structure, reference: &Structure, reflected: bool, max_iterations: usize) -> Option<(f64, Vec<usize>)> {
+    let positions = this.oriented_positions(reflected);
+    let frame_this = principal_frame(&positions, &this.weights);
+    let frame_ref = principal_frame(&reference.positions, &reference.weights);
+
+    let mut best: Option<(f64, Vec<usize>)> = None;
+    for r in cube_rotations() {
+        let rot = frame_ref.transpose() * r * frame_this;
+        let mut current: Vec<Point3> = positions.iter().map(|&p| (rot * Vector3f::from(p)).into()).collect();
+        let mut mapping = vec![];
+        let mut rmsd = f64::INFINITY;
+        for _ in 0..max_iterations {
+            let new_mapping = assign_atoms(&current, this, reference);
+            if new_mapping == mapping {
+                break;
+            }
+            mapping = new_mapping;
+            (rmsd, current) = superimpose_mapped(&positions, &reference.positions, &this.weights, &mapping);
+        }
+        if best.as_ref().is_none_or(|(x, _)| rmsd < *x) {
+            best = Some((rmsd, mapping));
+        }
+    }
+    best
+}
+
+/// Enumerate atom mappings from graph isomorphisms with matched elements.
+fn isomorphic_mappings(mol: &Molecule, mol_ref: &Molecule, max_isomorphisms: usize) -> Vec<Vec<usize>> {
+    let (g1, _) = mol.compact_graph();
+    let (g2, _) = mol_ref.compact_graph();
+    let (g1, g2) = (&g1, &g2);
+    let mut node_match = |a: &&crate::Atom, b: &&crate::Atom| a.kind() == b.kind();
+    let mut edge_match = |_: &&crate::Bond, _: &&crate::Bond| true;
+    if g1.edge_count() != g2.edge_count() {
+        return vec![];
+    }
+    algo::subgraph_isomorphisms_iter(&g1, &g2, &mut node_match, &mut edge_match)
+        .map(|iter| iter.take(max_isomorphisms).collect())
+        .unwrap_or_default()
+}
+// 691f1f9e ends here
+
+// [[file:../gchemol-core.note::59cc44d0][59cc44d0]]
+impl Molecule {
+    /// Return the superposition RMSD onto `mol_ref` with atom mapping
+    /// found automatically, irrespective of atom orders in both
+    /// molecules. Neither molecule will be changed. Return None if two
+    /// molecules have different compositions.
+    ///
+    /// # NOTE
+    /// * Atom mappings from graph isomorphisms with matched elements will
+    ///   be evaluated first if both molecules have bonds. Otherwise, the
+    ///   mapping is found by Hungarian assignment of atoms in the same
+    ///   element.
+    /// * Heavy atoms have more weights.
+    pub fn rmsd_with_mapping(&self, mol_ref: &Molecule, options: &RmsdOptions) -> Option<RmsdMapping> {
+        if self.natoms() == 0 || self.reduced_symbols() != mol_ref.reduced_symbols() {
+            return None;
+        }
+
+        let this = Structure::from_molecule(self);
+        let reference = Structure::from_molecule(mol_ref);
+        let handedness: &[bool] = if options.allow_reflection { &[false, true] } else { &[false] };
+
+        let mappings = if options.use_bonds && self.nbonds() > 0 {
+            isomorphic_mappings(self, mol_ref, options.max_isomorphisms)
+        } else {
+            vec![]
+        };
+
+        let mut best: Option<(f64, Vec<usize>, bool)> = None;
+        for &reflected in handedness {
+            let candidate = if mappings.is_empty() {
+                align_by_assignment(&this, &reference, reflected, options.max_iterations)
+            } else {
+                let positions = this.oriented_positions(reflected);
+                mappings
+                    .iter()
+                    .map(|m| (superimpose_mapped(&positions, &reference.positions, &this.weights, m).0, m.clone()))
+                    .min_by(|a, b| a.0.total_cmp(&b.0))
+            };
+            if let Some((rmsd, mapping)) = candidate {
+                if best.as_ref().is_none_or(|(x, ..)| rmsd < *x) {
+                    best = Some((rmsd, mapping, reflected));
+                }
+            }
+        }
+
+        let (_, mapping, reflected) = best?;
+        let positions = this.oriented_positions(reflected);
+        let (rmsd, positions) = superimpose_mapped(&positions, &reference.positions, &this.weights, &mapping);
+        let numbers_ref = mol_ref.numbers().collect_vec();
+        let mapping = self.numbers().zip(mapping).map(|(i, j)| (i, numbers_ref[j])).collect();
+        RmsdMapping {
+            rmsd,
+            mapping,
+            reflected,
+            positions,
+        }
+        .into()
+    }
+}
+// 59cc44d0 ends here
+
+// [[file:../gchemol-core.note::329ea5e7][329ea5e7]]
+#[test]
+fn test_rmsd_with_mapping() {
+    use crate::{Atom, Rotation, RotationCenter};
+    use vecfx::approx::assert_relative_eq;
+
+    assert_eq!(hungarian(&[vec![4.0, 1.0, 3.0], vec![2.0, 0.0, 5.0], vec![3.0, 2.0, 2.0]]), vec![1, 0, 2]);
+
+    // shuffled and rotated methane
+    let mol_ref = Molecule::from_database("CH4");
+    let mut mol = mol_ref.clone();
+    mol.rotate(&Rotation::from_axis_angle([1.0, 2.0, 3.0], 1.2), RotationCenter::CenterOfMass);
+    mol.translate([1.0, 2.0, 3.0]);
+    mol.reorder(&[3, 1, 2, 5, 4]);
+    let options = RmsdOptions::default();
+    let r = mol.rmsd_with_mapping(&mol_ref, &options).unwrap();
+    assert_relative_eq!(r.rmsd, 0.0, epsilon = 1e-3);
+    assert!(!r.reflected);
+
+    // the same with bonds
+    let mut mol_ref = mol_ref;
+    mol_ref.rebond();
+    mol.rebond();
+    let r = mol.rmsd_with_mapping(&mol_ref, &options).unwrap();
+    assert_relative_eq!(r.rmsd, 0.0, epsilon = 1e-3);
+    assert_eq!(r.mapping[0], (1, 2));
+
+    // enantiomers of CHFClBr
+    let atoms = vec![
+        Atom::new("C", [0.0, 0.0, 0.0]),
+        Atom::new("H", [0.63, 0.63, 0.63]),
+        Atom::new("F", [-0.8, -0.8, 0.8]),
+        Atom::new("Cl", [-1.0, 1.0, -1.0]),
+        Atom::new("Br", [1.1, -1.1, -1.1]),
+    ];
+    let mol1 = Molecule::from_atoms(atoms);
+    let mut mol2 = mol1.clone();
+    mol2.set_positions(mol1.positions().map(|[x, y, z]| [x, y, -z]));
+    let r = mol2.rmsd_with_mapping(&mol1, &options).unwrap();
+    assert!(r.rmsd > 0.1);
+    let options = RmsdOptions {
+        allow_reflection: true,
+        ..Default::default()
+    };
+    let r = mol2.rmsd_with_mapping(&mol1, &options).unwrap();
+    assert_relative_eq!(r.rmsd, 0.0, epsilon = 1e-3);
+    assert!(r.reflected);
+
+    // different compositions
+    let mol3 = Molecule::from_database("H2O");
+    assert!(mol3.rmsd_with_mapping(&mol1, &options).is_none());
+}
+// 329ea5e7 ends here
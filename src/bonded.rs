@@ -0,0 +1,178 @@
+// [[file:../gchemol-core.note::85a83aad][85a83aad]]
+//! Bonded interaction terms for molecular mechanics: angles, proper and
+//! improper dihedrals, 1-4 pairs and exclusions.
+// 85a83aad ends here
+
+// [[file:../gchemol-core.note::75fc0d88][75fc0d88]]
+use crate::common::*;
+use crate::{Molecule, Point3};
+
+use gchemol_geometry::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+// 75fc0d88 ends here
+
+// [[file:../gchemol-core.note::ed154b8b][ed154b8b]]
+impl Molecule {
+    /// Bonded atoms of each atom, in ascending order.
+    fn bonded_atoms(&self) -> BTreeMap<usize, Vec<usize>> {
+        self.numbers().map(|i| (i, self.connected(i).sorted().collect())).collect()
+    }
+
+    /// Return all unique bond angles as `[i, j, k]` with `j` the central
+    /// atom and `i < k`.
+    pub fn angles(&self) -> impl Iterator<Item = [usize; 3]> {
+        self.bonded_atoms()
+            .into_iter()
+            .flat_map(|(j, nn)| nn.into_iter().tuple_combinations().map(move |(i, k)| [i, j, k]))
+    }
+
+    /// Return all unique proper dihedrals as `[i, j, k, l]` with `i-j`,
+    /// `j-k`, `k-l` bonded and `j < k`. Dihedrals in three-membered
+    /// rings (`i == l`) are excluded.
+    pub fn proper_dihedrals(&self) -> impl Iterator<Item = [usize; 4]> {
+        let bonded = self.bonded_atoms();
+        let mut dihedrals = vec![];
+        for (j, k, _) in self.bonds() {
+            let (j, k) = (j.min(k), j.max(k));
+            for &i in bonded[&j].iter().filter(|&&i| i != k) {
+                for &l in bonded[&k].iter().filter(|&&l| l != j && l != i) {
+                    dihedrals.push([i, j, k, l]);
+                }
+            }
+        }
+        dihedrals.sort_unstable();
+        dihedrals.into_iter()
+    }
+
+    /// Return all improper dihedrals as `[i, j, k, l]` with `i` the
+    /// central atom bonded to exactly three atoms `j < k < l`, such as
+    /// the carbon in a carbonyl group.
+    pub fn improper_dihedrals(&self) -> impl Iterator<Item = [usize; 4]> {
+        self.bonded_atoms().into_iter().filter_map(|(i, nn)| match nn[..] {
+            [j, k, l] => Some([i, j, k, l]),
+            _ => None,
+        })
+    }
+
+    /// Return the atoms within two bonds of each atom (1-2 and 1-3
+    /// neighbors), which are usually excluded from nonbonded
+    /// interactions.
+    pub fn exclusions(&self) -> BTreeMap<usize, BTreeSet<usize>> {
+        let bonded = self.bonded_atoms();
+        bonded
+            .iter()
+            .map(|(&i, nn)| {
+                let mut excluded: BTreeSet<_> = nn.iter().copied().collect();
+                for j in nn {
+                    excluded.extend(bonded[j].iter().copied().filter(|&k| k != i));
+                }
+                (i, excluded)
+            })
+            .collect()
+    }
+
+    /// Return unique 1-4 pairs `(i, l)` with `i < l` from the ends of
+    /// proper dihedrals. Pairs that are also 1-2 or 1-3 neighbors, as in
+    /// four- or five-membered rings, are not included.
+    pub fn pairs_14(&self) -> impl Iterator<Item = (usize, usize)> {
+        let exclusions = self.exclusions();
+        self.proper_dihedrals()
+            .map(|[i, _, _, l]| (i.min(l), i.max(l)))
+            .filter(move |(i, l)| !exclusions[i].contains(l))
+            .sorted()
+            .dedup()
+    }
+
+    /// Return positions of bonded atoms in `path` as a continuous chain:
+    /// each atom is placed at the periodic image nearest to the previous
+    /// one.
+    fn unwrapped_positions(&self, path: &[usize]) -> Option<Vec<Point3>> {
+        let mut positions: Vec<Point3> = vec![];
+        for &i in path {
+            let p = self.get_atom(i)?.position();
+            match (positions.last(), self.lattice) {
+                (Some(&q), Some(lat)) => {
+                    let d = lat.apply_mic([p[0] - q[0], p[1] - q[1], p[2] - q[2]]);
+                    positions.push([q[0] + d[0], q[1] + d[1], q[2] + d[2]]);
+                }
+                _ => positions.push(p),
+            }
+        }
+        Some(positions)
+    }
+
+    /// Return the angle of three atoms `i`, `j`, `k` in radians under the
+    /// minimum image convention, which is correct for atoms bonded across
+    /// the periodic boundary. Return None if any serial number is invalid.
+    pub fn get_angle_mic(&self, i: usize, j: usize, k: usize) -> Option<f64> {
+        let p = self.unwrapped_positions(&[i, j, k])?;
+        p[0].angle(p[1], p[2]).into()
+    }
+
+    /// Return the torsion angle of four atoms `i`, `j`, `k`, `l` in
+    /// radians under the minimum image convention. Return None if any
+    /// serial number is invalid.
+    pub fn get_torsion_mic(&self, i: usize, j: usize, k: usize, l: usize) -> Option<f64> {
+        let p = self.unwrapped_positions(&[i, j, k, l])?;
+        p[0].torsion(p[1], p[2], p[3]).into()
+    }
+}
+// ed154b8b ends here
+
+// [[file:../gchemol-core.note::3c82314c][3c82314c]]
+#[test]
+fn test_bonded_terms() {
+    use crate::{Atom, Bond, Lattice};
+    use vecfx::approx::assert_relative_eq;
+
+    // ethylene-like skeleton: C1=C2, H3 H4 on C1, H5 H6 on C2
+    let atoms = [
+        ("C", [0.0, 0.0, 0.0]),
+        ("C", [1.3, 0.0, 0.0]),
+        ("H", [-0.6, 0.9, 0.0]),
+        ("H", [-0.6, -0.9, 0.0]),
+        ("H", [1.9, 0.9, 0.0]),
+        ("H", [1.9, -0.9, 0.0]),
+    ];
+    let mut mol = Molecule::from_atoms(atoms.iter().map(|&(s, p)| Atom::new(s, p)));
+    mol.add_bond(1, 2, Bond::double());
+    for (i, j) in [(1, 3), (1, 4), (2, 5), (2, 6)] {
+        mol.add_bond(i, j, Bond::single());
+    }
+
+    assert_eq!(mol.angles().count(), 6);
+    assert!(mol.angles().any(|a| a == [3, 1, 4]));
+    assert_eq!(mol.proper_dihedrals().collect_vec(), vec![[3, 1, 2, 5], [3, 1, 2, 6], [4, 1, 2, 5], [4, 1, 2, 6]]);
+    assert_eq!(mol.improper_dihedrals().collect_vec(), vec![[1, 2, 3, 4], [2, 1, 5, 6]]);
+    assert_eq!(mol.pairs_14().collect_vec(), vec![(3, 5), (3, 6), (4, 5), (4, 6)]);
+    let exclusions = mol.exclusions();
+    assert_eq!(exclusions[&3].iter().copied().collect_vec(), vec![1, 2, 4]);
+    assert_relative_eq!(mol.get_torsion_mic(3, 1, 2, 5).unwrap(), 0.0, epsilon = 1e-8);
+
+    // cyclopropane carbons: no proper dihedrals, all pairs are 1-2
+    let mut ring = Molecule::from_atoms((0..3).map(|i| Atom::new("C", [i as f64, 0.0, 0.0])));
+    for i in 1..=3 {
+        ring.add_bond(i, i % 3 + 1, Bond::single());
+    }
+    assert!(ring.proper_dihedrals().next().is_none());
+    assert!(ring.pairs_14().next().is_none());
+    assert_eq!(ring.angles().count(), 3);
+
+    // a bent chain bonded across the periodic boundary
+    let mut mol = Molecule::from_atoms(vec![
+        Atom::new("O", [9.5, 0.0, 0.0]),
+        Atom::new("C", [0.5, 0.0, 0.0]),
+        Atom::new("C", [0.5, 1.0, 0.0]),
+    ]);
+    mol.add_bond(1, 2, Bond::single());
+    mol.add_bond(2, 3, Bond::single());
+    assert_relative_eq!(mol.get_angle(1, 2, 3).unwrap().to_degrees(), 90.0, epsilon = 1e-8);
+    mol.set_lattice(Lattice::from_params(10.0, 10.0, 10.0, 90.0, 90.0, 90.0));
+    assert_relative_eq!(mol.get_angle_mic(1, 2, 3).unwrap().to_degrees(), 90.0, epsilon = 1e-8);
+    mol.set_position(1, [9.5, 1.0, 1.0]);
+    assert_eq!(mol.angles().collect_vec(), vec![[1, 2, 3]]);
+    let expected = (1.0 / 3f64.sqrt()).acos();
+    assert_relative_eq!(mol.get_angle_mic(1, 2, 3).unwrap(), expected, epsilon = 1e-8);
+    assert!((mol.get_angle(1, 2, 3).unwrap() - expected).abs() > 0.1);
+}
+// 3c82314c ends here
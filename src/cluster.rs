@@ -0,0 +1,326 @@
+// [[file:../gchemol-core.note::74dd865c][74dd865c]]
+//! Clustering of conformers or trajectory frames by RMSD
+// 74dd865c ends here
+
+// [[file:../gchemol-core.note::31d251d1][31d251d1]]
+use crate::common::*;
+use crate::trajectory::Trajectory;
+use crate::Molecule;
+
+use gchemol_geometry::Superimpose;
+// 31d251d1 ends here
+
+// [[file:../gchemol-core.note::18add776][18add776]]
+/// The distance between two clusters in hierarchical clustering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Linkage {
+    /// The minimum RMSD between members.
+    Single,
+    /// The mean RMSD between members.
+    #[default]
+    Average,
+    /// The maximum RMSD between members.
+    Complete,
+}
+
+/// Clustering algorithms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ClusterMethod {
+    /// Visit structures in order: a structure joins the nearest cluster
+    /// whose leader is within threshold, or starts a new cluster as its
+    /// leader.
+    #[default]
+    Leader,
+    /// Agglomerative hierarchical clustering, merging the closest pair
+    /// of clusters until their distance exceeds threshold.
+    Hierarchical(Linkage),
+}
+
+/// Options for clustering by RMSD.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClusterOptions {
+    /// The clustering algorithm.
+    pub method: ClusterMethod,
+
+    /// RMSD threshold for structures in the same cluster.
+    pub threshold: f64,
+
+    /// Serial numbers of atoms for alignment and RMSD. All atoms are
+    /// used if not set.
+    pub selection: Option<Vec<usize>>,
+}
+
+impl Default for ClusterOptions {
+    fn default() -> Self {
+        Self {
+            method: ClusterMethod::default(),
+            threshold: 0.5,
+            selection: None,
+        }
+    }
+}
+
+/// A cluster of structures.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cluster {
+    /// Indices of member structures in ascending order.
+    pub members: Vec<usize>,
+
+    /// Index of the representative structure: the leader in leader
+    /// clustering, or the member with the least total RMSD to others in
+    /// hierarchical clustering.
+    pub representative: usize,
+}
+
+/// Selected positions of each structure, with mass weights.
+struct Structures {
+    positions: Vec<Vec<[f64; 3]>>,
+    weights: Vec<f64>,
+}
+
+impl Structures {
+    fn new<'a>(parent: &Molecule, frames: impl Iterator<Item = &'a [[f64; 3]]>, selection: Option<&[usize]>) -> Result<Self> {
+        let numbers: Vec<_> = parent.numbers().collect();
+        let indices: Vec<usize> = match selection {
+            Some(selected) => selected
+                .iter()
+                .map(|sn| numbers.iter().position(|x| x == sn).ok_or_else(|| format_err!("invalid atom sn: {sn}")))
+                .collect::<Result<_>>()?,
+            None => (0..numbers.len()).collect(),
+        };
+        ensure!(!indices.is_empty(), "no atoms for rmsd");
+        let weights = indices
+            .iter()
+            .map(|&i| parent.get_atom_unchecked(numbers[i]).get_mass().unwrap_or(1.0))
+            .collect();
+        let positions = frames.map(|p| indices.iter().map(|&i| p[i]).collect()).collect();
+        Ok(Self { positions, weights })
+    }
+
+    fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Superposition RMSD between structures `i` and `j`.
+    fn rmsd(&self, i: usize, j: usize) -> f64 {
+        Superimpose::new(&self.positions[i]).onto(&self.positions[j], Some(&self.weights)).rmsd
+    }
+
+    fn cluster(&self, options: &ClusterOptions) -> Vec<Cluster> {
+        let mut clusters = match options.method {
+            ClusterMethod::Leader => self.leader_clustering(options.threshold),
+            ClusterMethod::Hierarchical(linkage) => self.hierarchical_clustering(options.threshold, linkage),
+        };
+        clusters.sort_by_key(|c| c.members[0]);
+        clusters
+    }
+
+    fn leader_clustering(&self, threshold: f64) -> Vec<Cluster> {
+        let mut clusters: Vec<Cluster> = vec![];
+        for i in 0..self.len() {
+            let nearest = clusters
+                .iter_mut()
+                .map(|c| (self.rmsd(i, c.representative), c))
+                .filter(|(d, _)| *d <= threshold)
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            match nearest {
+                Some((_, c)) => c.members.push(i),
+                None => clusters.push(Cluster {
+                    members: vec![i],
+                    representative: i,
+                }),
+            }
+        }
+        clusters
+    }
+
+    fn hierarchical_clustering(&self, threshold: f64, linkage: Linkage) -> Vec<Cluster> {
+        let n = self.len();
+        let mut d = vec![vec![0.0; n]; n];
+        for (i, j) in (0..n).tuple_combinations() {
+            d[i][j] = self.rmsd(i, j);
+            d[j][i] = d[i][j];
+        }
+        // distances between clusters, updated on merging by Lance-Williams
+        // formula
+        let mut dc = d.clone();
+        let mut groups: Vec<Vec<usize>> = (0..n).map(|i| vec![i]).collect();
+        let mut active = vec![true; n];
+        loop {
+            let closest = (0..n)
+                .filter(|&a| active[a])
+                .tuple_combinations()
+                .filter(|&(_, b)| active[b])
+                .map(|(a, b)| (dc[a][b], a, b))
+                .min_by(|x, y| x.0.total_cmp(&y.0));
+            let (a, b) = match closest {
+                Some((dab, a, b)) if dab <= threshold => (a, b),
+                _ => break,
+            };
+            let (na, nb) = (groups[a].len() as f64, groups[b].len() as f64);
+            for k in (0..n).filter(|&k| active[k] && k != a && k != b) {
+                let (dka, dkb) = (dc[k][a], dc[k][b]);
+                let dk = match linkage {
+                    Linkage::Single => dka.min(dkb),
+                    Linkage::Complete => dka.max(dkb),
+                    Linkage::Average => (na * dka + nb * dkb) / (na + nb),
+                };
+                dc[k][a] = dk;
+                dc[a][k] = dk;
+            }
+            let merged = std::mem::take(&mut groups[b]);
+            groups[a].extend(merged);
+            active[b] = false;
+        }
+
+        groups
+            .into_iter()
+            .filter(|members| !members.is_empty())
+            .map(|mut members| {
+                members.sort_unstable();
+                let representative = *members
+                    .iter()
+                    .min_by(|&&i, &&j| {
+                        let si: f64 = members.iter().map(|&k| d[i][k]).sum();
+                        let sj: f64 = members.iter().map(|&k| d[j][k]).sum();
+                        si.total_cmp(&sj)
+                    })
+                    .unwrap();
+                Cluster { members, representative }
+            })
+            .collect()
+    }
+}
+
+/// Indices of structures to keep after removing near-duplicates: the
+/// representatives of clusters, in original order.
+fn unique_indices(clusters: &[Cluster]) -> Vec<usize> {
+    clusters.iter().map(|c| c.representative).sorted().collect()
+}
+
+impl Trajectory {
+    /// Cluster frames by superposition RMSD between each pair of frames.
+    /// Frames are indexed from 0.
+    ///
+    /// # NOTE
+    /// * Superposition uses proper rotations only, so mirror images are
+    ///   not treated as the same.
+    /// * Hierarchical clustering computes all pairwise RMSD and keeps a
+    ///   distance matrix between clusters, which takes quadratic memory
+    ///   and cubic time in the number of frames.
+    pub fn cluster_frames(&self, options: &ClusterOptions) -> Result<Vec<Cluster>> {
+        let frames = self.frames.iter().map(|conf| conf.positions());
+        let structures = Structures::new(self.parent(), frames, options.selection.as_deref())?;
+        Ok(structures.cluster(options))
+    }
+
+    /// Remove near-duplicate frames, keeping the representative of each
+    /// cluster found with `options`. Return the number of removed frames.
+    pub fn dedup_frames(&mut self, options: &ClusterOptions) -> Result<usize> {
+        let keep = unique_indices(&self.cluster_frames(options)?);
+        let n = self.nframes();
+        let mut k = 0;
+        self.frames.retain(|_| {
+            k += 1;
+            keep.binary_search(&(k - 1)).is_ok()
+        });
+        Ok(n - self.nframes())
+    }
+}
+
+impl Molecule {
+    /// Cluster molecules with the same atoms in the same order (such as
+    /// conformers) by superposition RMSD. Molecules are indexed from 0.
+    /// See [Trajectory::cluster_frames](trajectory/struct.Trajectory.html#method.cluster_frames)
+    /// for details.
+    pub fn cluster_by_rmsd(mols: &[Molecule], options: &ClusterOptions) -> Result<Vec<Cluster>> {
+        let Some(first) = mols.first() else {
+            return Ok(vec![]);
+        };
+        if let Some(i) = mols.iter().position(|m| !first.matching_configuration(m)) {
+            bail!("molecule {i} does not match the first one");
+        }
+        let positions: Vec<Vec<_>> = mols.iter().map(|m| m.positions().collect()).collect();
+        let structures = Structures::new(first, positions.iter().map(|p| p.as_slice()), options.selection.as_deref())?;
+        Ok(structures.cluster(options))
+    }
+
+    /// Remove near-duplicate molecules from `mols`, keeping the
+    /// representative of each cluster. Return the number of removed
+    /// molecules.
+    pub fn dedup_by_rmsd(mols: &mut Vec<Molecule>, options: &ClusterOptions) -> Result<usize> {
+        let keep = unique_indices(&Self::cluster_by_rmsd(mols, options)?);
+        let n = mols.len();
+        let mut k = 0;
+        mols.retain(|_| {
+            k += 1;
+            keep.binary_search(&(k - 1)).is_ok()
+        });
+        Ok(n - mols.len())
+    }
+}
+// 18add776 ends here
+
+// [[file:../gchemol-core.note::c903c534][c903c534]]
+#[test]
+fn test_cluster_by_rmsd() {
+    use std::convert::TryFrom;
+
+    // two conformers of CH4 (one hydrogen displaced), each with rigidly
+    // moved copies and small noise
+    let mol = Molecule::from_database("CH4");
+    let mut distorted = mol.clone();
+    let [x, y, z] = distorted.get_atom(2).unwrap().position();
+    distorted.set_position(2, [x + 1.0, y, z]);
+    let mols: Vec<_> = [&mol, &distorted, &mol, &distorted, &mol]
+        .iter()
+        .enumerate()
+        .map(|(i, m)| {
+            let mut m = (*m).clone();
+            m.translate([i as f64, 0.0, 0.0]);
+            let [x, y, z] = m.get_atom(3).unwrap().position();
+            m.set_position(3, [x, y + 0.01 * i as f64, z]);
+            m
+        })
+        .collect();
+
+    let mut options = ClusterOptions {
+        threshold: 0.1,
+        ..Default::default()
+    };
+    let clusters = Molecule::cluster_by_rmsd(&mols, &options).unwrap();
+    assert_eq!(clusters.len(), 2);
+    assert_eq!(clusters[0].members, vec![0, 2, 4]);
+    assert_eq!(clusters[0].representative, 0);
+    assert_eq!(clusters[1].members, vec![1, 3]);
+
+    for linkage in [Linkage::Single, Linkage::Average, Linkage::Complete] {
+        options.method = ClusterMethod::Hierarchical(linkage);
+        let clusters = Molecule::cluster_by_rmsd(&mols, &options).unwrap();
+        assert_eq!(clusters.iter().map(|c| c.members.clone()).collect_vec(), vec![vec![0, 2, 4], vec![1, 3]]);
+        // the medoid of the first cluster
+        assert_eq!(clusters[0].representative, 2);
+    }
+    options.threshold = 1e-4;
+    assert_eq!(Molecule::cluster_by_rmsd(&mols, &options).unwrap().len(), 5);
+
+    // drop near-duplicates
+    let mut traj = Trajectory::try_from(mols.clone()).unwrap();
+    options.method = ClusterMethod::Leader;
+    options.threshold = 0.1;
+    assert_eq!(traj.cluster_frames(&options).unwrap().len(), 2);
+    assert_eq!(traj.dedup_frames(&options).unwrap(), 3);
+    assert_eq!(traj.nframes(), 2);
+    let mut unique = mols.clone();
+    assert_eq!(Molecule::dedup_by_rmsd(&mut unique, &options).unwrap(), 3);
+    assert_eq!(unique.len(), 2);
+
+    // fit on selected atoms only: all conformers are the same
+    options.selection = Some(vec![1, 3, 4, 5]);
+    assert_eq!(Molecule::cluster_by_rmsd(&mols, &options).unwrap().len(), 1);
+    let mut bad = mols.clone();
+    bad[1].get_atom_mut(1).unwrap().set_symbol("Si");
+    assert!(Molecule::cluster_by_rmsd(&bad, &options).is_err());
+}
+// c903c534 ends here
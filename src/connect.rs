@@ -19,18 +19,19 @@ enum BondingScheme {
     Multiwfn,
 }
 
-#[serde(default)]
+/// Options for creating bonds from interatomic distances.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RebondOptions {
-    // The distance tolerance for determine bonded or not between two
-    // atoms. Only relevant for rebond in Jmol scheme.
+    /// The distance tolerance for determine bonded or not between two
+    /// atoms. Only relevant for rebond in Jmol scheme.
     pub bond_tolerance: Option<f64>,
 
-    // Ignore periodic lattice when create bonds
+    /// Ignore periodic lattice when create bonds
     pub ignore_pbc: bool,
 
-    // The distance cutoff for searching nearest neighbors. Beyond
-    // this value, the bonding is not considered.
+    /// The distance cutoff for searching nearest neighbors. Beyond
+    /// this value, the bonding is not considered.
     pub distance_cutoff: Option<f64>,
 
     /// The scale factor for covalent or vdw radius. Only relevant for
@@ -0,0 +1,157 @@
+// [[file:../gchemol-core.note::7aba3779][7aba3779]]
+//! Topological descriptors of `Molecule` based on graph distances
+// 7aba3779 ends here
+
+// [[file:../gchemol-core.note::1bb6b399][1bb6b399]]
+use crate::Molecule;
+
+use gchemol_graph::petgraph::visit::EdgeRef;
+use std::collections::VecDeque;
+// 1bb6b399 ends here
+
+// [[file:../gchemol-core.note::6f845401][6f845401]]
+/// Adjacency list of atoms in serial number order.
+fn adjacency_list(mol: &Molecule) -> Vec<Vec<usize>> {
+    let (graph, _) = mol.compact_graph();
+    let mut neighbors = vec![vec![]; graph.node_count()];
+    for e in graph.edge_references() {
+        let (i, j) = (e.source().index(), e.target().index());
+        neighbors[i].push(j);
+        neighbors[j].push(i);
+    }
+    neighbors
+}
+
+/// All-pairs shortest path lengths by breadth-first search from each
+/// atom.
+fn shortest_path_lengths(neighbors: &[Vec<usize>]) -> Vec<Vec<Option<usize>>> {
+    let n = neighbors.len();
+    let mut matrix = vec![vec![None; n]; n];
+    for (i, row) in matrix.iter_mut().enumerate() {
+        row[i] = Some(0);
+        let mut queue = VecDeque::from([i]);
+        while let Some(j) = queue.pop_front() {
+            let d = row[j].unwrap() + 1;
+            for &k in &neighbors[j] {
+                if row[k].is_none() {
+                    row[k] = Some(d);
+                    queue.push_back(k);
+                }
+            }
+        }
+    }
+    matrix
+}
+
+/// Topological descriptors
+impl Molecule {
+    /// Return the topological distance matrix, i.e. the shortest
+    /// distances counted in number of chemical bonds between all pairs
+    /// of atoms. Rows and columns are in the order of atom serial
+    /// numbers. The distance is None if two atoms are not connected.
+    pub fn topological_distance_matrix(&self) -> Vec<Vec<Option<usize>>> {
+        shortest_path_lengths(&adjacency_list(self))
+    }
+
+    /// Return the Wiener index: the sum of topological distances over
+    /// all pairs of connected atoms.
+    pub fn wiener_index(&self) -> usize {
+        let dm = self.topological_distance_matrix();
+        let total: usize = dm.iter().flatten().flatten().sum();
+        total / 2
+    }
+
+    /// Return the Randić connectivity index: the sum of
+    /// `1/sqrt(d_i * d_j)` over all bonds, where `d_i` is the number of
+    /// atoms bonded to atom `i`.
+    ///
+    /// # NOTE
+    /// * Hydrogen atoms are counted if present. Remove them first for the
+    ///   conventional hydrogen-suppressed value.
+    pub fn randic_index(&self) -> f64 {
+        let neighbors = adjacency_list(self);
+        let mut index = 0.0;
+        for (i, nn) in neighbors.iter().enumerate() {
+            for &j in nn.iter().filter(|&&j| j > i) {
+                index += 1.0 / ((nn.len() * neighbors[j].len()) as f64).sqrt();
+            }
+        }
+        index
+    }
+
+    /// Return the Balaban J index. Return None if the molecule is not
+    /// connected or has no bonds.
+    ///
+    /// J = m / (μ + 1) Σ (s_i s_j)^(-1/2), where the sum runs over all
+    /// bonds, m is the number of bonds, μ the cyclomatic number, and s_i
+    /// the sum of topological distances from atom i.
+    pub fn balaban_index(&self) -> Option<f64> {
+        let neighbors = adjacency_list(self);
+        let dm = shortest_path_lengths(&neighbors);
+        let sums: Vec<usize> = dm.iter().map(|row| row.iter().copied().sum::<Option<usize>>()).collect::<Option<_>>()?;
+
+        let n = neighbors.len();
+        let m = self.nbonds();
+        if m == 0 {
+            return None;
+        }
+        let mu = m + 1 - n;
+        let mut j = 0.0;
+        for (a, nn) in neighbors.iter().enumerate() {
+            for &b in nn.iter().filter(|&&b| b > a) {
+                j += 1.0 / ((sums[a] * sums[b]) as f64).sqrt();
+            }
+        }
+        Some(m as f64 / (mu + 1) as f64 * j)
+    }
+
+    /// Return the eccentricity of each atom in serial number order: the
+    /// largest topological distance to any atom in the same fragment.
+    pub fn eccentricities(&self) -> Vec<usize> {
+        self.topological_distance_matrix()
+            .iter()
+            .map(|row| row.iter().flatten().copied().max().unwrap_or(0))
+            .collect()
+    }
+
+    /// Return the graph diameter: the largest eccentricity. For
+    /// molecules with multiple fragments, the largest one among all
+    /// fragments is returned.
+    pub fn graph_diameter(&self) -> usize {
+        self.eccentricities().into_iter().max().unwrap_or(0)
+    }
+}
+// 6f845401 ends here
+
+// [[file:../gchemol-core.note::2e7680f1][2e7680f1]]
+#[test]
+fn test_topological_descriptors() {
+    use crate::{Atom, Bond};
+    use vecfx::approx::assert_relative_eq;
+
+    // n-butane carbon skeleton: 1-2-3-4
+    let mut mol = Molecule::from_atoms((0..4).map(|i| Atom::new("C", [i as f64 * 1.5, 0.0, 0.0])));
+    for i in 1..4 {
+        mol.add_bond(i, i + 1, Bond::single());
+    }
+    let dm = mol.topological_distance_matrix();
+    assert_eq!(dm[0], vec![Some(0), Some(1), Some(2), Some(3)]);
+    assert_eq!(mol.wiener_index(), 10);
+    assert_relative_eq!(mol.randic_index(), 1.0 / 2f64.sqrt() * 2.0 + 0.5, epsilon = 1e-8);
+    assert_relative_eq!(mol.balaban_index().unwrap(), 1.9747, epsilon = 1e-4);
+    assert_eq!(mol.eccentricities(), vec![3, 2, 2, 3]);
+    assert_eq!(mol.graph_diameter(), 3);
+
+    // cyclobutane ring
+    mol.add_bond(4, 1, Bond::single());
+    assert_eq!(mol.wiener_index(), 8);
+    assert_relative_eq!(mol.balaban_index().unwrap(), 2.0, epsilon = 1e-8);
+    assert_eq!(mol.graph_diameter(), 2);
+
+    // disconnected atom
+    mol.add_atom(5, Atom::new("O", [9.0, 0.0, 0.0]));
+    assert_eq!(mol.topological_distance_matrix()[0][4], None);
+    assert_eq!(mol.wiener_index(), 8);
+    assert!(mol.balaban_index().is_none());
+}
+// 2e7680f1 ends here
@@ -0,0 +1,274 @@
+// [[file:../gchemol-core.note::bf786c4a][bf786c4a]]
+//! Bit-vector fingerprints of `Molecule` for similarity search
+// bf786c4a ends here
+
+// [[file:../gchemol-core.note::84f98884][84f98884]]
+use crate::common::*;
+use crate::hash::Fnv1a;
+use crate::isomorphism::bond_label;
+use crate::substructure::find_ring_bonds;
+use crate::Molecule;
+
+use gchemol_graph::petgraph::visit::EdgeRef;
+use std::collections::HashMap;
+// 84f98884 ends here
+
+// [[file:../gchemol-core.note::650d5040][650d5040]]
+/// A fixed-length bit vector fingerprint.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fingerprint {
+    nbits: usize,
+    blocks: Vec<u64>,
+}
+
+impl Fingerprint {
+    /// Create a fingerprint of `nbits` bits, with all bits off.
+    ///
+    /// # Panics
+    ///
+    /// * panics if `nbits` is zero.
+    pub fn new(nbits: usize) -> Self {
+        assert!(nbits > 0, "invalid fingerprint length: {nbits}");
+        Self {
+            nbits,
+            blocks: vec![0; nbits.div_ceil(64)],
+        }
+    }
+
+    /// Return the length of the fingerprint in bits.
+    pub fn nbits(&self) -> usize {
+        self.nbits
+    }
+
+    /// Turn on bit `i`.
+    pub fn set(&mut self, i: usize) {
+        assert!(i < self.nbits, "bit index out of range: {i}");
+        self.blocks[i / 64] |= 1 << (i % 64);
+    }
+
+    /// Test if bit `i` is on.
+    pub fn is_set(&self, i: usize) -> bool {
+        i < self.nbits && self.blocks[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    /// Return the number of bits that are on.
+    pub fn count_ones(&self) -> usize {
+        self.blocks.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    /// Iterate over indices of bits that are on.
+    pub fn on_bits(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.nbits).filter(move |&i| self.is_set(i))
+    }
+
+    /// Return the number of bits on in both fingerprints.
+    fn count_common(&self, other: &Self) -> usize {
+        assert_eq!(self.nbits, other.nbits, "fingerprint lengths differ");
+        self.blocks.iter().zip(&other.blocks).map(|(a, b)| (a & b).count_ones() as usize).sum()
+    }
+
+    /// Return the Tanimoto (Jaccard) similarity: c / (a + b - c). Return
+    /// 0 if no bit is on in either fingerprint.
+    ///
+    /// # Panics
+    ///
+    /// * panics if the fingerprint lengths differ.
+    pub fn tanimoto(&self, other: &Self) -> f64 {
+        let c = self.count_common(other);
+        let union = self.count_ones() + other.count_ones() - c;
+        if union == 0 {
+            0.0
+        } else {
+            c as f64 / union as f64
+        }
+    }
+
+    /// Return the Dice similarity: 2c / (a + b). Return 0 if no bit is on
+    /// in either fingerprint.
+    ///
+    /// # Panics
+    ///
+    /// * panics if the fingerprint lengths differ.
+    pub fn dice(&self, other: &Self) -> f64 {
+        let c = self.count_common(other);
+        let total = self.count_ones() + other.count_ones();
+        if total == 0 {
+            0.0
+        } else {
+            2.0 * c as f64 / total as f64
+        }
+    }
+
+    /// Fold hashed feature into the bit vector.
+    fn set_hashed(&mut self, h: u64) {
+        let i = (h % self.nbits as u64) as usize;
+        self.set(i);
+    }
+}
+// 650d5040 ends here
+
+// [[file:../gchemol-core.note::ca2263bb][ca2263bb]]
+/// Heavy atoms and their invariants in the spirit of ECFP: atomic
+/// number, number of heavy atom neighbors, number of hydrogens, partial
+/// charge and ring membership.
+fn heavy_atom_invariants(mol: &Molecule) -> HashMap<usize, u64> {
+    let (graph, numbers) = mol.compact_graph();
+    let ring = find_ring_bonds(&graph);
+    let in_ring: HashMap<_, _> = graph
+        .node_indices()
+        .map(|n| (numbers[n.index()], graph.edges(n).any(|e| ring[e.id().index()])))
+        .collect();
+
+    mol.atoms()
+        .filter(|(_, a)| a.number() != 1)
+        .map(|(sn, a)| {
+            let nh = mol.connected(sn).filter(|&j| mol.get_atom(j).unwrap().number() == 1).count();
+            let degree = mol.connected(sn).count() - nh;
+            let charge = (a.partial_charge.unwrap_or(0.0) * 1000.0).round() as i64;
+            let mut hasher = Fnv1a::new();
+            for x in [a.number() as i64, degree as i64, nh as i64, charge, in_ring[&sn] as i64] {
+                hasher.write_i64(x);
+            }
+            hasher.write_str(a.symbol());
+            (sn, hasher.finish())
+        })
+        .collect()
+}
+
+fn bond_token(mol: &Molecule, i: usize, j: usize) -> i64 {
+    bond_label(mol.get_bond(i, j).expect("no bond").kind()) as i64
+}
+
+/// Extend `path` of heavy atoms and hash it when the path is unique up
+/// to direction.
+fn walk_paths(mol: &Molecule, heavy: &HashMap<usize, u64>, path: &mut Vec<usize>, max_length: usize, fp: &mut Fingerprint) {
+    let tokens = |path: &mut dyn Iterator<Item = &usize>| -> Vec<i64> {
+        let mut tokens = vec![];
+        let mut prev: Option<usize> = None;
+        for &sn in path {
+            if let Some(p) = prev {
+                tokens.push(bond_token(mol, p, sn));
+            }
+            tokens.push(mol.get_atom(sn).unwrap().number() as i64);
+            prev = Some(sn);
+        }
+        tokens
+    };
+    let forward = tokens(&mut path.iter());
+    let backward = tokens(&mut path.iter().rev());
+    let mut hasher = Fnv1a::new();
+    for x in forward.min(backward) {
+        hasher.write_i64(x);
+    }
+    fp.set_hashed(hasher.finish());
+
+    if path.len() > max_length {
+        return;
+    }
+    let last = *path.last().unwrap();
+    for j in mol.connected(last).filter(|j| heavy.contains_key(j)).collect_vec() {
+        if !path.contains(&j) {
+            path.push(j);
+            walk_paths(mol, heavy, path, max_length, fp);
+            path.pop();
+        }
+    }
+}
+
+/// Molecular fingerprints
+impl Molecule {
+    /// Return the circular fingerprint in the style of ECFP (extended
+    /// connectivity fingerprint), by updating atom identifiers with those
+    /// of bonded atoms `radius` times, in a fingerprint of `nbits` bits.
+    /// ECFP4 corresponds to `radius` = 2.
+    ///
+    /// # NOTE
+    /// * Hydrogen atoms are not taken as centers, but counted in atom
+    ///   invariants.
+    /// * Bond kinds are taken as they are in `Molecule`.
+    pub fn morgan_fingerprint(&self, radius: usize, nbits: usize) -> Fingerprint {
+        let mut fp = Fingerprint::new(nbits);
+        let mut ids = heavy_atom_invariants(self);
+        ids.values().for_each(|&h| fp.set_hashed(h));
+        for _ in 0..radius {
+            let updated: HashMap<_, _> = ids
+                .iter()
+                .map(|(&i, &id)| {
+                    let mut env: Vec<_> = self
+                        .connected(i)
+                        .filter_map(|j| Some((bond_token(self, i, j), *ids.get(&j)?)))
+                        .collect();
+                    env.sort_unstable();
+                    let mut hasher = Fnv1a::new();
+                    hasher.write_i64(id as i64);
+                    for (b, x) in env {
+                        hasher.write_i64(b);
+                        hasher.write_i64(x as i64);
+                    }
+                    (i, hasher.finish())
+                })
+                .collect();
+            ids = updated;
+            ids.values().for_each(|&h| fp.set_hashed(h));
+        }
+        fp
+    }
+
+    /// Return the linear path fingerprint, by hashing all paths of heavy
+    /// atoms of up to `max_length` bonds into a fingerprint of `nbits`
+    /// bits. A path is encoded by the element and bond kind sequence.
+    pub fn path_fingerprint(&self, max_length: usize, nbits: usize) -> Fingerprint {
+        let mut fp = Fingerprint::new(nbits);
+        let heavy = heavy_atom_invariants(self);
+        for &i in heavy.keys() {
+            walk_paths(self, &heavy, &mut vec![i], max_length, &mut fp);
+        }
+        fp
+    }
+}
+// ca2263bb ends here
+
+// [[file:../gchemol-core.note::b8b9f675][b8b9f675]]
+#[test]
+fn test_fingerprint() {
+    use crate::{Atom, Bond};
+
+    // carbon chains with n atoms, and an optional hydroxyl group
+    let chain = |n: usize, oh: bool| {
+        let mut mol = Molecule::from_atoms((0..n).map(|i| Atom::new("C", [i as f64 * 1.5, 0.0, 0.0])));
+        for i in 1..n {
+            mol.add_bond(i, i + 1, Bond::single());
+        }
+        if oh {
+            mol.add_atom(n + 1, Atom::new("O", [n as f64 * 1.5, 0.0, 0.0]));
+            mol.add_bond(n, n + 1, Bond::single());
+        }
+        mol
+    };
+    let propanol = chain(3, true);
+    let butanol = chain(4, true);
+    let butane = chain(4, false);
+
+    for fp in [|m: &Molecule| m.morgan_fingerprint(2, 1024), |m: &Molecule| m.path_fingerprint(5, 1024)] {
+        let fp1 = fp(&propanol);
+        let fp2 = fp(&butanol);
+        let fp3 = fp(&butane);
+        assert!(fp1.count_ones() > 0);
+        assert_eq!(fp1.tanimoto(&fp1), 1.0);
+        assert_eq!(fp1.dice(&fp1), 1.0);
+        assert!(fp1.tanimoto(&fp2) > fp1.tanimoto(&fp3));
+        assert!(fp2.dice(&fp3) >= fp2.tanimoto(&fp3));
+
+        // independent of atom ordering
+        let mut mol = butanol.clone();
+        mol.reorder(&[5, 3, 1, 4, 2]);
+        assert_eq!(fp(&mol), fp2);
+    }
+
+    let mut fp = Fingerprint::new(100);
+    fp.set(3);
+    fp.set(99);
+    assert_eq!(fp.on_bits().collect_vec(), vec![3, 99]);
+    assert_eq!(Fingerprint::new(100).tanimoto(&Fingerprint::new(100)), 0.0);
+}
+// b8b9f675 ends here
@@ -29,7 +29,7 @@ where
     counts
 }
 
-fn get_reduced_formula<I>(symbols: I) -> String
+pub(crate) fn get_reduced_formula<I>(symbols: I) -> String
 where
     I: IntoIterator,
     I::Item: std::fmt::Display,
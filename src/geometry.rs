@@ -157,3 +157,139 @@ impl Molecule {
     }
 }
 // b3195ba1 ends here
+
+// [[file:../gchemol-core.note::4a115435][4a115435]]
+use vecfx::nalgebra as na;
+use vecfx::Matrix3f;
+
+/// A rigid-body rotation in 3D space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rotation {
+    matrix: Matrix3f,
+}
+
+impl Rotation {
+    /// Construct from a 3x3 rotation matrix `m` in row major. The matrix
+    /// is not checked for orthogonality.
+    pub fn from_matrix(m: [[f64; 3]; 3]) -> Self {
+        Self {
+            matrix: Matrix3f::from_row_slice(&m.concat()),
+        }
+    }
+
+    /// Construct from rotation by `angle` in radians about `axis`
+    /// (right-hand rule).
+    pub fn from_axis_angle<P: Into<Vector3f>>(axis: P, angle: f64) -> Self {
+        let axis = na::Unit::new_normalize(axis.into());
+        let matrix = na::Rotation3::from_axis_angle(&axis, angle).into_inner();
+        Self { matrix }
+    }
+
+    /// Construct from a quaternion in [w, x, y, z], which will be
+    /// normalized.
+    pub fn from_quaternion(q: [f64; 4]) -> Self {
+        let [w, x, y, z] = q;
+        let q = na::UnitQuaternion::from_quaternion(na::Quaternion::new(w, x, y, z));
+        let matrix = q.to_rotation_matrix().into_inner();
+        Self { matrix }
+    }
+
+    /// Return the rotation matrix in row major.
+    pub fn matrix(&self) -> [[f64; 3]; 3] {
+        let m = self.matrix;
+        [0, 1, 2].map(|i| [m[(i, 0)], m[(i, 1)], m[(i, 2)]])
+    }
+
+    /// Rotate point `p` about `center`.
+    pub fn apply<P: Into<Vector3f>>(&self, p: P, center: Point3) -> Point3 {
+        let center: Vector3f = center.into();
+        (self.matrix * (p.into() - center) + center).into()
+    }
+}
+
+/// The center for rigid-body rotation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RotationCenter {
+    /// A point in Cartesian coordinates
+    Point(Point3),
+    /// The center of mass of rotated atoms
+    CenterOfMass,
+    /// The center of geometry of rotated atoms
+    CenterOfGeometry,
+}
+
+/// Rigid-body rotation
+impl Molecule {
+    /// Rotate the whole molecule by `rotation` about `center`.
+    pub fn rotate(&mut self, rotation: &Rotation, center: RotationCenter) {
+        let atoms = self.serial_numbers().collect_vec();
+        self.rotate_atoms(&atoms, rotation, center, false);
+    }
+
+    /// Rotate `atoms` specified in serial numbers by `rotation` about
+    /// `center`. The centers of mass or geometry are calculated using
+    /// the selected atoms only.
+    ///
+    /// # Parameters
+    /// * respect_freezing: keep freezing coordinates of atoms unchanged.
+    ///
+    /// # Panics
+    /// * panic if any atom in `atoms` does not exist.
+    pub fn rotate_atoms(&mut self, atoms: &[usize], rotation: &Rotation, center: RotationCenter, respect_freezing: bool) {
+        let positions = atoms.iter().map(|&i| self.get_atom_unchecked(i).position()).collect_vec();
+        let center = match center {
+            RotationCenter::Point(p) => p,
+            RotationCenter::CenterOfGeometry => positions.center_of_geometry(),
+            RotationCenter::CenterOfMass => {
+                let masses = atoms.iter().map(|&i| self.get_atom_unchecked(i).get_mass().unwrap_or_default()).collect_vec();
+                positions.center_of_mass(&masses)
+            }
+        };
+
+        for (&i, p) in atoms.iter().zip(positions) {
+            let p = rotation.apply(p, center);
+            let atom = self.get_atom_unchecked_mut(i);
+            if respect_freezing {
+                atom.update_position(p);
+            } else {
+                atom.set_position(p);
+            }
+        }
+    }
+}
+// 4a115435 ends here
+
+// [[file:../gchemol-core.note::74cfa5b5][74cfa5b5]]
+#[test]
+fn test_rotation() {
+    use std::f64::consts::PI;
+    use vecfx::approx::assert_relative_eq;
+
+    let r1 = Rotation::from_axis_angle([0.0, 0.0, 1.0], PI / 2.0);
+    let r2 = Rotation::from_quaternion([(PI / 4.0).cos(), 0.0, 0.0, (PI / 4.0).sin()]);
+    let r3 = Rotation::from_matrix([[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+    let p = r1.apply([1.0, 0.0, 0.0], [0.0; 3]);
+    assert_relative_eq!(p.as_slice(), [0.0, 1.0, 0.0].as_slice(), epsilon = 1e-8);
+    for r in [r2, r3] {
+        assert_relative_eq!(r.matrix().concat().as_slice(), r1.matrix().concat().as_slice(), epsilon = 1e-8);
+    }
+
+    // rotation keeps the internal geometry
+    let mut mol = Molecule::from_database("H2O");
+    let mol0 = mol.clone();
+    let com = mol.center_of_mass();
+    mol.rotate(&r1, RotationCenter::CenterOfMass);
+    assert_relative_eq!(mol.center_of_mass().as_slice(), com.as_slice(), epsilon = 1e-8);
+    assert_relative_eq!(mol.get_distance(1, 2).unwrap(), mol0.get_distance(1, 2).unwrap(), epsilon = 1e-8);
+    assert_relative_eq!(mol.get_angle(2, 1, 3).unwrap(), mol0.get_angle(2, 1, 3).unwrap(), epsilon = 1e-8);
+
+    // rotate a subset of atoms with freezing coordinates
+    let mut mol = mol0.clone();
+    mol.get_atom_mut(3).unwrap().set_freezing([true; 3]);
+    mol.rotate_atoms(&[2, 3], &r1, RotationCenter::Point(mol0.get_atom(1).unwrap().position()), true);
+    assert_eq!(mol.get_atom(1).unwrap().position(), mol0.get_atom(1).unwrap().position());
+    assert_eq!(mol.get_atom(3).unwrap().position(), mol0.get_atom(3).unwrap().position());
+    assert_relative_eq!(mol.get_distance(1, 2).unwrap(), mol0.get_distance(1, 2).unwrap(), epsilon = 1e-8);
+    assert_ne!(mol.get_atom(2).unwrap().position(), mol0.get_atom(2).unwrap().position());
+}
+// 74cfa5b5 ends here
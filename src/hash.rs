@@ -0,0 +1,226 @@
+// [[file:../gchemol-core.note::6c4ca3ae][6c4ca3ae]]
+//! Structural hash of `Molecule` for deduplication
+// 6c4ca3ae ends here
+
+// [[file:../gchemol-core.note::f6087ffd][f6087ffd]]
+use crate::common::*;
+use crate::isomorphism::bond_label;
+use crate::order::canonical_labels_by;
+use crate::Molecule;
+
+use gchemol_geometry::prelude::*;
+use std::collections::HashMap;
+// f6087ffd ends here
+
+// [[file:../gchemol-core.note::cd4abdbd][cd4abdbd]]
+/// The structural features taken into account in
+/// [structure_hash](struct.Molecule.html#method.structure_hash).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HashMode {
+    /// Element symbols and bonds only.
+    #[default]
+    Topology,
+    /// Topology plus interatomic distances and lattice parameters,
+    /// rounded to `precision` (Å for lengths, degree for angles).
+    Geometry {
+        /// Rounding precision.
+        precision: f64,
+    },
+}
+
+/// A deterministic hash value of `Molecule` structure, invariant to
+/// atom ordering and rigid motion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct StructureHash(u64);
+
+impl StructureHash {
+    /// Return the raw hash value.
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl std::fmt::Display for StructureHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// 64-bit FNV-1a hasher. Unlike `DefaultHasher`, the result is stable
+/// across Rust releases, so that the hash can be stored in database.
+pub(crate) struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub(crate) fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub(crate) fn write_i64(&mut self, x: i64) {
+        self.write(&x.to_le_bytes());
+    }
+
+    pub(crate) fn write_str(&mut self, s: &str) {
+        self.write(s.as_bytes());
+        // separator
+        self.write(&[0xff]);
+    }
+}
+
+fn round(x: f64, precision: f64) -> i64 {
+    (x / precision).round() as i64
+}
+
+/// Hash element symbols and bonds in canonical order.
+fn hash_topology(mol: &Molecule, hasher: &mut Fnv1a) {
+    let labels = canonical_labels_by(mol, |a| (std::cmp::Reverse(a.number()), a.symbol().to_owned()));
+    let labels: HashMap<_, _> = mol.numbers().zip(labels).collect();
+
+    let mut symbols = vec![""; labels.len()];
+    for (sn, atom) in mol.atoms() {
+        symbols[labels[&sn]] = atom.symbol();
+    }
+    hasher.write_i64(symbols.len() as i64);
+    for s in symbols {
+        hasher.write_str(s);
+    }
+
+    let mut bonds: Vec<_> = mol
+        .bonds()
+        .map(|(i, j, b)| {
+            let (li, lj) = (labels[&i], labels[&j]);
+            (li.min(lj), li.max(lj), bond_label(b.kind()))
+        })
+        .collect();
+    bonds.sort_unstable();
+    hasher.write_i64(bonds.len() as i64);
+    for (i, j, b) in bonds {
+        hasher.write_i64(i as i64);
+        hasher.write_i64(j as i64);
+        hasher.write_i64(b as i64);
+    }
+}
+
+/// Hash sorted interatomic distances for each pair of elements, and
+/// lattice parameters if periodic.
+fn hash_geometry(mol: &Molecule, precision: f64, hasher: &mut Fnv1a) {
+    let atoms: Vec<_> = mol.atoms().map(|(_, a)| (a.symbol(), a.position())).collect();
+    let mut distances = vec![];
+    for (i, &(si, pi)) in atoms.iter().enumerate() {
+        for &(sj, pj) in &atoms[i + 1..] {
+            let d = match mol.lattice {
+                Some(lat) => lat.distance(pi, pj),
+                None => pi.distance(pj),
+            };
+            let pair = if si <= sj { (si, sj) } else { (sj, si) };
+            distances.push((pair, round(d, precision)));
+        }
+    }
+    distances.sort_unstable();
+    for ((si, sj), d) in distances {
+        hasher.write_str(si);
+        hasher.write_str(sj);
+        hasher.write_i64(d);
+    }
+
+    if let Some(lat) = mol.lattice {
+        hasher.write_str("lattice");
+        for x in lat.lengths().into_iter().chain(lat.angles()) {
+            hasher.write_i64(round(x, precision));
+        }
+    }
+}
+
+impl Molecule {
+    /// Return a deterministic hash of the structure, which is invariant
+    /// to atom ordering and rigid motion, and can be used as a key for
+    /// deduplication in `HashMap`.
+    ///
+    /// # NOTE
+    /// * In `HashMode::Topology`, only element symbols and bonds
+    ///   (including bond kinds) are considered.
+    /// * In `HashMode::Geometry`, rounded distances of all atom pairs are
+    ///   also considered, using the minimum image convention for periodic
+    ///   structure. Structures close to a rounding boundary may still get
+    ///   different hashes.
+    /// * Return error if `precision` of `HashMode::Geometry` is not
+    ///   positive.
+    pub fn structure_hash(&self, mode: HashMode) -> Result<StructureHash> {
+        if let HashMode::Geometry { precision } = mode {
+            ensure!(precision > 0.0, "invalid precision: {precision}");
+        }
+        let mut hasher = Fnv1a::new();
+        hash_topology(self, &mut hasher);
+        if let HashMode::Geometry { precision } = mode {
+            hash_geometry(self, precision, &mut hasher);
+        }
+        Ok(StructureHash(hasher.finish()))
+    }
+}
+// cd4abdbd ends here
+
+// [[file:../gchemol-core.note::c7919429][c7919429]]
+#[test]
+fn test_structure_hash() {
+    use crate::{Bond, Lattice};
+
+    let mut mol = Molecule::from_database("CH4");
+    for i in 2..=5 {
+        mol.add_bond(1, i, Bond::single());
+    }
+    let geom = HashMode::Geometry { precision: 0.01 };
+    let h1 = mol.structure_hash(HashMode::Topology).unwrap();
+    let g1 = mol.structure_hash(geom).unwrap();
+    assert_ne!(h1, g1);
+
+    // invariant to atom ordering and rigid motion
+    let mut mol2 = mol.clone();
+    mol2.reorder(&[5, 4, 3, 2, 1]);
+    mol2.translate([1.0, 2.0, 3.0]);
+    assert_eq!(mol2.structure_hash(HashMode::Topology).unwrap(), h1);
+    assert_eq!(mol2.structure_hash(geom).unwrap(), g1);
+
+    // distorted geometry with the same topology
+    let mut mol3 = mol.clone();
+    mol3.set_position(2, [1.2, 0.0, 0.0]);
+    assert_eq!(mol3.structure_hash(HashMode::Topology).unwrap(), h1);
+    assert_ne!(mol3.structure_hash(geom).unwrap(), g1);
+
+    // bonds matter
+    let mut mol4 = mol.clone();
+    mol4.remove_bond(1, 2);
+    assert_ne!(mol4.structure_hash(HashMode::Topology).unwrap(), h1);
+
+    // periodic images: moving an atom by a lattice vector
+    let mut mol5 = mol.clone();
+    mol5.set_lattice(Lattice::from_params(5.0, 5.0, 5.0, 90.0, 90.0, 90.0));
+    let g5 = mol5.structure_hash(geom).unwrap();
+    assert_ne!(g5, g1);
+    let [x, y, z] = mol5.get_atom(2).unwrap().position();
+    mol5.set_position(2, [x + 5.0, y, z]);
+    assert_eq!(mol5.structure_hash(geom).unwrap(), g5);
+
+    // usable as HashMap key
+    let mut db = std::collections::HashMap::new();
+    for m in [&mol, &mol2, &mol3] {
+        db.entry(m.structure_hash(geom).unwrap()).or_insert_with(Vec::new).push(m.title());
+    }
+    assert_eq!(db.len(), 2);
+    assert_eq!(format!("{}", h1).len(), 16);
+
+    // invalid precision
+    for precision in [0.0, -0.01, f64::NAN] {
+        assert!(mol.structure_hash(HashMode::Geometry { precision }).is_err());
+    }
+}
+// c7919429 ends here
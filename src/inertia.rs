@@ -1,6 +1,9 @@
 // [[file:../gchemol-core.note::*imports][imports:1]]
+use crate::common::*;
 use crate::molecule::Molecule;
 use crate::Point3;
+
+use vecfx::{Matrix3f, Vector3f};
 // imports:1 ends here
 
 // [[file:../gchemol-core.note::*core][core:1]]
@@ -58,3 +61,174 @@ impl Molecule {
     }
 }
 // core:1 ends here
+
+// [[file:../gchemol-core.note::7abe5ee0][7abe5ee0]]
+/// Planck constant in J·s
+const PLANCK: f64 = 6.62607015e-34;
+/// Atomic mass unit in kg
+pub(crate) const AMU: f64 = 1.66053906660e-27;
+/// Speed of light in cm/s
+const SPEED_OF_LIGHT: f64 = 2.99792458e10;
+
+/// The relative tolerance for treating two principal moments as
+/// degenerate.
+const MOMENT_TOLERANCE: f64 = 1e-3;
+
+/// Classification of molecular rotors by principal moments of inertia.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RotorType {
+    /// Ia = 0, Ib = Ic
+    Linear,
+    /// Ia = Ib = Ic, including single atom
+    Spherical,
+    /// Ia = Ib < Ic (oblate), or Ia < Ib = Ic (prolate)
+    Symmetric,
+    /// Ia < Ib < Ic
+    Asymmetric,
+}
+
+fn is_degenerate(a: f64, b: f64, moments: &[f64; 3]) -> bool {
+    (a - b).abs() <= MOMENT_TOLERANCE * moments[2]
+}
+
+/// Rotational constants in GHz from principal moments in amu·Å².
+fn rotational_constants_hz(moments: [f64; 3]) -> [f64; 3] {
+    let b = |i: f64| {
+        if i > 0.0 {
+            PLANCK / (8.0 * std::f64::consts::PI.powi(2) * i * AMU * 1e-20)
+        } else {
+            f64::INFINITY
+        }
+    };
+    [b(moments[0]), b(moments[1]), b(moments[2])]
+}
+
+/// Principal axes of inertia
+impl Molecule {
+    /// Return the principal moments of inertia in ascending order, in
+    /// unit of amu·Å².
+    pub fn principal_moments(&self) -> [f64; 3] {
+        self.principal_axes_of_inertia().0
+    }
+
+    /// Return the principal axes of inertia as unit vectors, corresponding
+    /// to the principal moments in ascending order.
+    pub fn principal_axes(&self) -> [Point3; 3] {
+        self.principal_axes_of_inertia().1
+    }
+
+    fn principal_axes_of_inertia(&self) -> ([f64; 3], [Point3; 3]) {
+        let m = Matrix3f::from_row_slice(&self.inertia_matrix().concat());
+        let eigen = m.symmetric_eigen();
+        let order = (0..3)
+            .sorted_by(|&i, &j| eigen.eigenvalues[i].total_cmp(&eigen.eigenvalues[j]))
+            .collect_vec();
+        let mut moments = [0, 1, 2].map(|i| eigen.eigenvalues[order[i]]);
+        // remove numerical noise for linear molecule
+        if is_degenerate(moments[0], 0.0, &moments) {
+            moments[0] = 0.0;
+        }
+        let axes = [0, 1, 2].map(|i| eigen.eigenvectors.column(order[i]).into_owned().into());
+        (moments, axes)
+    }
+
+    /// Return rotational constants A, B, C in GHz. For zero moment of
+    /// inertia, the constant will be infinity.
+    pub fn rotational_constants(&self) -> [f64; 3] {
+        rotational_constants_hz(self.principal_moments()).map(|b| b * 1e-9)
+    }
+
+    /// Return rotational constants A, B, C in wavenumbers (cm⁻¹).
+    pub fn rotational_constants_in_wavenumbers(&self) -> [f64; 3] {
+        rotational_constants_hz(self.principal_moments()).map(|b| b / SPEED_OF_LIGHT)
+    }
+
+    /// Classify the molecule as a linear, spherical, symmetric or
+    /// asymmetric top rotor.
+    pub fn rotor_type(&self) -> RotorType {
+        let moments = self.principal_moments();
+        let [ia, ib, ic] = moments;
+        if is_degenerate(ia, ic, &moments) {
+            RotorType::Spherical
+        } else if is_degenerate(ia, 0.0, &moments) {
+            RotorType::Linear
+        } else if is_degenerate(ia, ib, &moments) || is_degenerate(ib, ic, &moments) {
+            RotorType::Symmetric
+        } else {
+            RotorType::Asymmetric
+        }
+    }
+
+    /// Move the center of mass to the origin, and align the molecule to its
+    /// principal axes, like the standard orientation in Gaussian.
+    ///
+    /// # NOTE
+    /// * The unique axis of linear or symmetric top molecule is aligned to z
+    ///   axis. Otherwise, the principal axes with ascending moments are
+    ///   aligned to x, y, z axes respectively.
+    /// * The structure will not be mirrored.
+    pub fn to_standard_orientation(&mut self) {
+        let (moments, axes) = self.principal_axes_of_inertia();
+        let [ia, ib, ic] = moments;
+        let [a, b, c] = axes.map(Vector3f::from);
+        let (x, y) = match self.rotor_type() {
+            // the unique axis has the smallest moment for prolate top
+            RotorType::Linear => (b, c),
+            RotorType::Symmetric if !is_degenerate(ia, ib, &moments) && is_degenerate(ib, ic, &moments) => (b, c),
+            _ => (a, b),
+        };
+        // right-handed frame without mirroring
+        let z = x.cross(&y);
+
+        let com: Vector3f = self.center_of_mass().into();
+        let positions: Vec<_> = self
+            .positions()
+            .map(|p| {
+                let p = Vector3f::from(p) - com;
+                [x.dot(&p), y.dot(&p), z.dot(&p)]
+            })
+            .collect();
+        self.set_positions(positions);
+    }
+}
+// 7abe5ee0 ends here
+
+// [[file:../gchemol-core.note::5494c2ad][5494c2ad]]
+#[test]
+fn test_principal_axes() {
+    use vecfx::approx::assert_relative_eq;
+
+    // water
+    let mut mol = Molecule::from_database("H2O");
+    assert_eq!(mol.rotor_type(), RotorType::Asymmetric);
+    let [a, b, c] = mol.rotational_constants_in_wavenumbers();
+    assert!(a > b && b > c);
+    let moments = mol.principal_moments();
+    let [ga, _, _] = mol.rotational_constants();
+    assert_relative_eq!(ga * moments[0], 505.379, epsilon = 1e-2);
+
+    mol.to_standard_orientation();
+    assert_relative_eq!(mol.center_of_mass().as_slice(), [0.0; 3].as_slice(), epsilon = 1e-8);
+    let m = mol.inertia_matrix();
+    for i in 0..3 {
+        assert_relative_eq!(m[i][i], moments[i], epsilon = 1e-6);
+        for j in 0..3 {
+            if i != j {
+                assert_relative_eq!(m[i][j], 0.0, epsilon = 1e-6);
+            }
+        }
+    }
+
+    let mut mol = Molecule::from_database("HCN");
+    assert_eq!(mol.rotor_type(), RotorType::Linear);
+    assert!(mol.rotational_constants()[0].is_infinite());
+    mol.to_standard_orientation();
+    for [x, y, _] in mol.positions() {
+        assert_relative_eq!(x, 0.0, epsilon = 1e-6);
+        assert_relative_eq!(y, 0.0, epsilon = 1e-6);
+    }
+
+    let mol = Molecule::from_database("CH4");
+    assert_eq!(mol.rotor_type(), RotorType::Spherical);
+}
+// 5494c2ad ends here
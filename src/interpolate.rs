@@ -0,0 +1,308 @@
+// [[file:../gchemol-core.note::5f5debd7][5f5debd7]]
+//! Interpolation of images between two structures, for NEB calculations
+// 5f5debd7 ends here
+
+// [[file:../gchemol-core.note::00dcb935][00dcb935]]
+use crate::common::*;
+use crate::trajectory::Trajectory;
+use crate::{Lattice, Molecule};
+
+use vecfx::Vector3f;
+// 00dcb935 ends here
+
+// [[file:../gchemol-core.note::d66660c9][d66660c9]]
+/// Methods for interpolating images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum InterpolationMethod {
+    /// Linear interpolation of Cartesian coordinates.
+    #[default]
+    Linear,
+    /// Linear interpolation of fractional coordinates and lattice
+    /// vectors, for periodic structures.
+    Fractional,
+    /// Image dependent pair potential (IDPP): starting from linear
+    /// interpolation, each image is relaxed to match pair distances
+    /// interpolated between reactant and product.
+    Idpp,
+}
+
+/// A builder for interpolating images between reactant and product,
+/// such as the initial path of NEB calculation.
+///
+/// # Example
+///
+/// ```ignore
+/// let traj = ImageInterpolation::new(&reactant, &product)
+///     .nimages(5)
+///     .method(InterpolationMethod::Idpp)
+///     .build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ImageInterpolation<'a> {
+    reactant: &'a Molecule,
+    product: &'a Molecule,
+    nimages: usize,
+    method: InterpolationMethod,
+    max_iterations: usize,
+    fmax: f64,
+}
+
+/// Positions of atoms in serial number order as vectors.
+fn positions_of(mol: &Molecule) -> Vec<Vector3f> {
+    mol.positions().map(Vector3f::from).collect()
+}
+
+/// Linearly interpolated lattice at `t`, if both lattices are defined.
+fn interpolate_lattice(a: Option<&Lattice>, b: Option<&Lattice>, t: f64) -> Option<Lattice> {
+    let (a, b) = (a?, b?);
+    Some(Lattice::from_matrix(a.matrix() * (1.0 - t) + b.matrix() * t))
+}
+
+/// Pair distances between all atoms, with the minimum image convention
+/// for periodic structure.
+fn pair_distances(positions: &[Vector3f], lattice: Option<&Lattice>) -> Vec<f64> {
+    (0..positions.len())
+        .tuple_combinations()
+        .map(|(i, j)| {
+            let r = positions[j] - positions[i];
+            lattice.map_or(r, |lat| lat.apply_mic(r)).norm()
+        })
+        .collect()
+}
+
+/// The IDPP objective and its gradient for `positions` with `targets`
+/// pair distances: S = sum (D_ij - d_ij)^2 / d_ij^4
+fn idpp_objective(positions: &[Vector3f], targets: &[f64], lattice: Option<&Lattice>) -> (f64, Vec<Vector3f>) {
+    let mut s = 0.0;
+    let mut gradient = vec![Vector3f::zeros(); positions.len()];
+    for ((i, j), &dt) in (0..positions.len()).tuple_combinations().zip(targets) {
+        let r = positions[j] - positions[i];
+        let r = lattice.map_or(r, |lat| lat.apply_mic(r));
+        let d = r.norm();
+        // overlapping atoms have no defined direction
+        if d < 1e-8 {
+            continue;
+        }
+        let x = dt - d;
+        s += x * x / d.powi(4);
+        let ds_dd = -2.0 * x / d.powi(4) - 4.0 * x * x / d.powi(5);
+        let g = r * (ds_dd / d);
+        gradient[j] += g;
+        gradient[i] -= g;
+    }
+    (s, gradient)
+}
+
+impl<'a> ImageInterpolation<'a> {
+    /// Interpolate between `reactant` and `product`, which must have the
+    /// same atoms in the same order.
+    pub fn new(reactant: &'a Molecule, product: &'a Molecule) -> Self {
+        Self {
+            reactant,
+            product,
+            nimages: 5,
+            method: InterpolationMethod::default(),
+            max_iterations: 1000,
+            fmax: 0.01,
+        }
+    }
+
+    /// Set the number of intermediate images, excluding reactant and
+    /// product. Defaults to 5.
+    pub fn nimages(mut self, n: usize) -> Self {
+        self.nimages = n;
+        self
+    }
+
+    /// Set the interpolation method. Defaults to linear interpolation.
+    pub fn method(mut self, method: InterpolationMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Set the maximum number of relaxation steps for each image in IDPP
+    /// interpolation. Defaults to 1000.
+    pub fn idpp_max_iterations(mut self, n: usize) -> Self {
+        self.max_iterations = n;
+        self
+    }
+
+    /// Set the convergence threshold of the largest gradient component of
+    /// IDPP objective. Defaults to 0.01.
+    pub fn idpp_fmax(mut self, fmax: f64) -> Self {
+        self.fmax = fmax;
+        self
+    }
+
+    /// Build the trajectory of images, with reactant as the first frame
+    /// and product as the last one.
+    ///
+    /// # NOTE
+    /// * Displacements of periodic structures follow the minimum image
+    ///   convention, so atoms may move across cell boundaries.
+    /// * Frozen coordinates of reactant atoms are kept unchanged in all
+    ///   intermediate images.
+    /// * In IDPP interpolation each image is relaxed independently on its
+    ///   own pair potential, without springs between images.
+    pub fn build(&self) -> Result<Trajectory> {
+        let (reactant, product) = (self.reactant, self.product);
+        ensure!(
+            reactant.matching_configuration(product),
+            "reactant and product have different atoms or periodicity"
+        );
+        if self.method == InterpolationMethod::Fractional {
+            ensure!(reactant.is_periodic(), "fractional interpolation requires periodic structures");
+        }
+
+        let r0 = positions_of(reactant);
+        let r1 = positions_of(product);
+        let (lat0, lat1) = (reactant.get_lattice(), product.get_lattice());
+        let frozen: Vec<_> = reactant.atoms().map(|(_, a)| a.freezing()).collect();
+        let (targets0, targets1) = if self.method == InterpolationMethod::Idpp {
+            (pair_distances(&r0, lat0), pair_distances(&r1, lat1))
+        } else {
+            (vec![], vec![])
+        };
+
+        let mut traj = Trajectory::new(reactant.clone());
+        traj.push(reactant)?;
+        let mut mol = reactant.clone();
+        let n = self.nimages + 1;
+        for k in 1..n {
+            let t = k as f64 / n as f64;
+            let lattice = interpolate_lattice(lat0, lat1, t);
+            let mut positions: Vec<Vector3f> = match self.method {
+                InterpolationMethod::Fractional => {
+                    let (lat0, lat1, lat) = (lat0.unwrap(), lat1.unwrap(), lattice.unwrap());
+                    r0.iter()
+                        .zip(&r1)
+                        .map(|(&p0, &p1)| {
+                            let f0 = lat0.to_frac(p0);
+                            let df = lat1.to_frac(p1) - f0;
+                            let df = df.map(|x| x - x.round());
+                            lat.to_cart(f0 + df * t)
+                        })
+                        .collect()
+                }
+                _ => r0
+                    .iter()
+                    .zip(&r1)
+                    .map(|(&p0, &p1)| {
+                        let d = p1 - p0;
+                        p0 + lat0.map_or(d, |lat| lat.apply_mic(d)) * t
+                    })
+                    .collect(),
+            };
+            for (p, (p0, mask)) in positions.iter_mut().zip(r0.iter().zip(&frozen)) {
+                (0..3).filter(|&x| mask[x]).for_each(|x| p[x] = p0[x]);
+            }
+            if self.method == InterpolationMethod::Idpp {
+                let targets: Vec<_> = targets0.iter().zip(&targets1).map(|(a, b)| a * (1.0 - t) + b * t).collect();
+                self.relax_idpp(&mut positions, &targets, lattice.as_ref(), &frozen);
+            }
+
+            mol.set_positions(positions);
+            if let Some(lat) = lattice {
+                mol.set_lattice(lat);
+            }
+            mol.set_title(format!("image {k}"));
+            traj.push(&mol)?;
+        }
+        traj.push(product)?;
+        Ok(traj)
+    }
+
+    /// Minimize IDPP objective by steepest descent with adaptive step
+    /// size, keeping frozen coordinates fixed.
+    fn relax_idpp(&self, positions: &mut [Vector3f], targets: &[f64], lattice: Option<&Lattice>, frozen: &[[bool; 3]]) {
+        const MAX_STEP: f64 = 0.1;
+        let mut alpha = 0.01;
+        let (mut s, mut gradient) = idpp_objective(positions, targets, lattice);
+        for _ in 0..self.max_iterations {
+            for (g, mask) in gradient.iter_mut().zip(frozen) {
+                (0..3).filter(|&x| mask[x]).for_each(|x| g[x] = 0.0);
+            }
+            let gmax = gradient.iter().map(|g| g.amax()).fold(0.0, f64::max);
+            if gmax < self.fmax {
+                break;
+            }
+            // limit the displacement of any atom
+            let dmax = gradient.iter().map(|g| g.norm()).fold(0.0, f64::max) * alpha;
+            let scale = if dmax > MAX_STEP { alpha * MAX_STEP / dmax } else { alpha };
+            let trial: Vec<_> = positions.iter().zip(&gradient).map(|(p, g)| p - g * scale).collect();
+            let (s_new, gradient_new) = idpp_objective(&trial, targets, lattice);
+            if s_new < s {
+                positions.copy_from_slice(&trial);
+                s = s_new;
+                gradient = gradient_new;
+                alpha *= 1.2;
+            } else {
+                alpha *= 0.5;
+            }
+        }
+    }
+}
+// d66660c9 ends here
+
+// [[file:../gchemol-core.note::7958263f][7958263f]]
+#[test]
+fn test_image_interpolation() {
+    use crate::Atom;
+    use vecfx::approx::assert_relative_eq;
+
+    // a diatomic rotating by 90 degrees, with a frozen spectator atom
+    let mut reactant = Molecule::from_atoms(vec![
+        Atom::new("H", [-0.5, 0.0, 0.0]),
+        Atom::new("H", [0.5, 0.0, 0.0]),
+        Atom::new("Ar", [0.0, 0.0, 5.0]),
+    ]);
+    reactant.get_atom_mut(3).unwrap().set_freezing([true; 3]);
+    let mut product = reactant.clone();
+    product.set_positions([[0.0, -0.5, 0.0], [0.0, 0.5, 0.0], [1.0, 0.0, 5.0]]);
+
+    let traj = ImageInterpolation::new(&reactant, &product).nimages(3).build().unwrap();
+    assert_eq!(traj.nframes(), 5);
+    let mid = traj[2].positions();
+    assert_relative_eq!(mid[0][0], -0.25, epsilon = 1e-8);
+    assert_relative_eq!(mid[1][1], 0.25, epsilon = 1e-8);
+    assert_eq!(mid[2], [0.0, 0.0, 5.0]);
+    assert_eq!(traj[4].positions()[2], [1.0, 0.0, 5.0]);
+    assert_eq!(traj.get(2).unwrap().to_molecule().title(), "image 2");
+
+    // IDPP keeps the bond length while rotating
+    let traj = ImageInterpolation::new(&reactant, &product)
+        .nimages(3)
+        .method(InterpolationMethod::Idpp)
+        .idpp_fmax(1e-6)
+        .build()
+        .unwrap();
+    let mid = traj[2].positions();
+    let d = Vector3f::from(mid[1]) - Vector3f::from(mid[0]);
+    assert_relative_eq!(d.norm(), 1.0, epsilon = 1e-4);
+    assert_eq!(mid[2], [0.0, 0.0, 5.0]);
+    assert!(ImageInterpolation::new(&reactant, &product)
+        .method(InterpolationMethod::Fractional)
+        .build()
+        .is_err());
+
+    // crossing the periodic boundary, with lattice expansion
+    let mut reactant = Molecule::from_atoms(vec![Atom::new("Li", [0.5, 1.0, 1.0])]);
+    reactant.set_lattice(Lattice::from_params(10.0, 10.0, 10.0, 90.0, 90.0, 90.0));
+    let mut product = reactant.clone();
+    product.set_positions([[9.5, 1.0, 1.0]]);
+    let traj = ImageInterpolation::new(&reactant, &product).nimages(1).build().unwrap();
+    assert_relative_eq!(traj[1].positions()[0][0], 0.0, epsilon = 1e-8);
+
+    product.set_lattice(Lattice::from_params(12.0, 10.0, 10.0, 90.0, 90.0, 90.0));
+    product.set_positions([[11.4, 1.0, 1.0]]);
+    let traj = ImageInterpolation::new(&reactant, &product)
+        .nimages(1)
+        .method(InterpolationMethod::Fractional)
+        .build()
+        .unwrap();
+    // fractional x from 0.05 to -0.05 in a cell of length 11
+    let lattice = traj[1].lattice().unwrap();
+    assert_relative_eq!(lattice.lengths()[0], 11.0, epsilon = 1e-8);
+    assert_relative_eq!(traj[1].positions()[0][0], 0.0, epsilon = 1e-8);
+}
+// 7958263f ends here
@@ -0,0 +1,190 @@
+// [[file:../gchemol-core.note::5630d622][5630d622]]
+//! Graph isomorphism and topological symmetry of `Molecule`
+// 5630d622 ends here
+
+// [[file:../gchemol-core.note::6d05868f][6d05868f]]
+use crate::common::*;
+use crate::{BondKind, Molecule};
+
+use gchemol_graph::petgraph::algo;
+use gchemol_graph::petgraph::graph::UnGraph;
+use gchemol_graph::petgraph::visit::EdgeRef;
+// 6d05868f ends here
+
+// [[file:../gchemol-core.note::4f49949f][4f49949f]]
+/// Replace `labels` with their ranks in sorted order, so that equal
+/// labels get the same rank.
+pub(crate) fn rank_labels<T: Ord>(labels: &[T]) -> Vec<usize> {
+    let mut sorted: Vec<_> = labels.iter().collect();
+    sorted.sort();
+    sorted.dedup();
+    labels.iter().map(|x| sorted.binary_search(&x).unwrap()).collect()
+}
+
+/// Refine atom colors iteratively by the colors of their neighbors and
+/// the bonds in between (the Weisfeiler-Lehman or Morgan algorithm),
+/// until the number of colors no longer increases.
+///
+/// `neighbors` is the adjacency list with bond labels; `colors` is the
+/// initial coloring. The returned colors are ranks of the refined labels,
+/// which do not depend on the order of atoms.
+pub(crate) fn refine_colors(neighbors: &[Vec<(usize, usize)>], colors: Vec<usize>) -> Vec<usize> {
+    let mut colors = rank_labels(&colors);
+    let mut ncolors = colors.iter().unique().count();
+    loop {
+        let labels: Vec<_> = neighbors
+            .iter()
+            .enumerate()
+            .map(|(i, nn)| {
+                let mut env: Vec<_> = nn.iter().map(|&(j, b)| (b, colors[j])).collect();
+                env.sort_unstable();
+                (colors[i], env)
+            })
+            .collect();
+        let refined = rank_labels(&labels);
+        let n = refined.iter().unique().count();
+        if n == ncolors {
+            return colors;
+        }
+        colors = refined;
+        ncolors = n;
+    }
+}
+
+/// Numeric label of a bond kind.
+pub(crate) fn bond_label(kind: BondKind) -> usize {
+    kind as usize
+}
+
+/// The bonding graph of `mol` labelled by refined atom colors and bond
+/// kinds, together with the atom serial number of each node.
+pub(crate) fn colored_graph(mol: &Molecule) -> (UnGraph<usize, usize>, Vec<usize>) {
+    let (graph, numbers) = mol.compact_graph();
+    let symbols: Vec<_> = graph.node_weights().map(|a| a.symbol()).collect();
+    let mut neighbors = vec![vec![]; graph.node_count()];
+    for e in graph.edge_references() {
+        let (i, j, b) = (e.source().index(), e.target().index(), bond_label(e.weight().kind()));
+        neighbors[i].push((j, b));
+        neighbors[j].push((i, b));
+    }
+    let colors = refine_colors(&neighbors, rank_labels(&symbols));
+    let graph = graph.map(|n, _| colors[n.index()], |_, b| bond_label(b.kind()));
+
+    (graph, numbers)
+}
+// 4f49949f ends here
+
+// [[file:../gchemol-core.note::622742b2][622742b2]]
+impl Molecule {
+    /// Test if `self` and `other` have the same bonding graph, in which
+    /// atoms are labelled by element and bonds by bond kind. Atom
+    /// ordering and geometry are ignored.
+    pub fn is_isomorphic_to(&self, other: &Molecule) -> bool {
+        if self.natoms() != other.natoms() || self.nbonds() != other.nbonds() {
+            return false;
+        }
+        let (g1, _) = self.compact_graph();
+        let (g2, _) = other.compact_graph();
+        let mut node_match = |a1: &&crate::Atom, a2: &&crate::Atom| a1.kind() == a2.kind();
+        let mut edge_match = |b1: &&crate::Bond, b2: &&crate::Bond| b1.kind() == b2.kind();
+        algo::is_isomorphic_matching(&&g1, &&g2, &mut node_match, &mut edge_match)
+    }
+
+    /// Group atoms into classes that are equivalent under automorphisms
+    /// of the bonding graph (atoms labelled by element and bonds by bond
+    /// kind). Return serial numbers of each class in ascending order,
+    /// with classes ordered by their first atom.
+    ///
+    /// Atoms in the same class are topologically equivalent, such as the
+    /// hydrogen atoms in a methyl group. Geometry is ignored.
+    pub fn topological_equivalence_classes(&self) -> Vec<Vec<usize>> {
+        let (graph, numbers) = colored_graph(self);
+        let graph = graph.map(|_, &c| (c, false), |_, &b| b);
+        let n = graph.node_count();
+
+        // atoms with different refined colors can never be equivalent;
+        // atoms with the same color are equivalent only if there is an
+        // automorphism mapping one onto the other.
+        let mut node_match = |a: &(usize, bool), b: &(usize, bool)| a == b;
+        let mut edge_match = |a: &usize, b: &usize| a == b;
+        let mut maps_onto = |i: usize, j: usize| {
+            let mut g1 = graph.clone();
+            let mut g2 = graph.clone();
+            g1[NodeIndex::new(i)].1 = true;
+            g2[NodeIndex::new(j)].1 = true;
+            algo::is_isomorphic_matching(&&g1, &&g2, &mut node_match, &mut edge_match)
+        };
+
+        let mut classes: Vec<Vec<usize>> = vec![];
+        for i in 0..n {
+            let color = graph[NodeIndex::new(i)].0;
+            let found = classes
+                .iter_mut()
+                .filter(|c| graph[NodeIndex::new(c[0])].0 == color)
+                .find(|c| maps_onto(c[0], i));
+            match found {
+                Some(c) => c.push(i),
+                None => classes.push(vec![i]),
+            }
+        }
+
+        classes
+            .into_iter()
+            .map(|c| c.into_iter().map(|i| numbers[i]).collect())
+            .collect()
+    }
+}
+// 622742b2 ends here
+
+// [[file:../gchemol-core.note::aa91664e][aa91664e]]
+#[test]
+fn test_isomorphism() {
+    use crate::{Atom, Bond};
+
+    // ethanol: C1 C2 O3 H4..H9
+    let atoms = [
+        ("C", [0.0, 0.0, 0.0]),
+        ("C", [1.5, 0.0, 0.0]),
+        ("O", [2.0, 1.3, 0.0]),
+        ("H", [-0.4, 1.0, 0.0]),
+        ("H", [-0.4, -0.5, 0.9]),
+        ("H", [-0.4, -0.5, -0.9]),
+        ("H", [1.9, -0.5, 0.9]),
+        ("H", [1.9, -0.5, -0.9]),
+        ("H", [3.0, 1.3, 0.0]),
+    ];
+    let bonds = [(1, 2), (2, 3), (1, 4), (1, 5), (1, 6), (2, 7), (2, 8), (3, 9)];
+    let mut mol = Molecule::from_atoms(atoms.iter().map(|&(s, p)| Atom::new(s, p)));
+    for &(i, j) in bonds.iter() {
+        mol.add_bond(i, j, Bond::single());
+    }
+
+    let classes = mol.topological_equivalence_classes();
+    assert_eq!(classes, vec![vec![1], vec![2], vec![3], vec![4, 5, 6], vec![7, 8], vec![9]]);
+
+    // renumbered copy is isomorphic
+    let mut mol2 = mol.clone();
+    mol2.reorder(&[9, 8, 7, 6, 5, 4, 3, 2, 1]);
+    assert!(mol.is_isomorphic_to(&mol2));
+
+    // dimethyl ether has the same formula but different bonding
+    let mut mol3 = mol.clone();
+    mol3.remove_bond(1, 2);
+    mol3.remove_bond(3, 9);
+    mol3.add_bond(1, 3, Bond::single());
+    mol3.add_bond(2, 9, Bond::single());
+    assert_eq!(mol.formula(), mol3.formula());
+    assert!(!mol.is_isomorphic_to(&mol3));
+    // different bond kind
+    let mut mol4 = mol.clone();
+    mol4.add_bond(1, 2, Bond::double());
+    assert!(!mol.is_isomorphic_to(&mol4));
+
+    // all atoms in a cyclohexane ring are equivalent
+    let mut ring = Molecule::from_atoms((0..6).map(|i| Atom::new("C", [i as f64, 0.0, 0.0])));
+    for i in 1..=6 {
+        ring.add_bond(i, i % 6 + 1, Bond::single());
+    }
+    assert_eq!(ring.topological_equivalence_classes(), vec![vec![1, 2, 3, 4, 5, 6]]);
+}
+// aa91664e ends here
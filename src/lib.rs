@@ -25,25 +25,61 @@ mod lattice;
 mod molecule;
 mod property;
 
+#[cfg(feature = "adhoc")]
+mod alignment;
+#[cfg(feature = "adhoc")]
+mod bonded;
 #[cfg(feature = "adhoc")]
 mod clean;
 #[cfg(feature = "adhoc")]
+mod cluster;
+#[cfg(feature = "adhoc")]
 mod connect;
 #[cfg(feature = "adhoc")]
+mod descriptor;
+#[cfg(feature = "adhoc")]
+mod fingerprint;
+#[cfg(feature = "adhoc")]
 mod freeze;
 #[cfg(feature = "adhoc")]
 mod geometry;
 #[cfg(feature = "adhoc")]
+mod hash;
+#[cfg(feature = "adhoc")]
 mod inertia;
 #[cfg(feature = "adhoc")]
+mod interpolate;
+#[cfg(feature = "adhoc")]
+mod isomorphism;
+#[cfg(feature = "adhoc")]
+mod msd;
+#[cfg(feature = "adhoc")]
 mod order;
 #[cfg(feature = "adhoc")]
+mod pointgroup;
+#[cfg(feature = "adhoc")]
+mod rdf;
+#[cfg(feature = "adhoc")]
+mod reaction;
+#[cfg(feature = "adhoc")]
+mod residue;
+#[cfg(feature = "adhoc")]
 mod ring;
 #[cfg(feature = "adhoc")]
 mod topology;
 
 #[cfg(feature = "adhoc")]
 mod selection;
+#[cfg(feature = "adhoc")]
+mod spacegroup;
+#[cfg(feature = "adhoc")]
+mod store;
+#[cfg(feature = "adhoc")]
+mod substructure;
+#[cfg(feature = "adhoc")]
+mod superposition;
+#[cfg(feature = "adhoc")]
+mod thermo;
 
 // #[cfg(feature = "adhoc")]
 // pub mod images;
@@ -80,4 +116,58 @@ pub use topology::AtomGroup;
 
 #[cfg(feature = "adhoc")]
 pub use crate::freeze::Mask;
+
+#[cfg(feature = "adhoc")]
+pub use crate::alignment::{RmsdMapping, RmsdOptions};
+
+#[cfg(feature = "adhoc")]
+pub use crate::cluster::{Cluster, ClusterMethod, ClusterOptions, Linkage};
+
+#[cfg(feature = "adhoc")]
+pub use crate::connect::RebondOptions;
+
+#[cfg(feature = "adhoc")]
+pub use crate::fingerprint::Fingerprint;
+
+#[cfg(feature = "adhoc")]
+pub use crate::geometry::{Rotation, RotationCenter};
+
+#[cfg(feature = "adhoc")]
+pub use crate::hash::{HashMode, StructureHash};
+
+#[cfg(feature = "adhoc")]
+pub use crate::inertia::RotorType;
+
+#[cfg(feature = "adhoc")]
+pub use crate::interpolate::{ImageInterpolation, InterpolationMethod};
+
+#[cfg(feature = "adhoc")]
+pub use crate::msd::{Msd, MsdOptions};
+
+#[cfg(feature = "adhoc")]
+pub use crate::pointgroup::{PointGroup, SymmetryElement};
+
+#[cfg(feature = "adhoc")]
+pub use crate::rdf::{Rdf, RdfOptions};
+
+#[cfg(feature = "adhoc")]
+pub use crate::reaction::{BondEvent, BondEventKind, ReactionAnalysis, ReactionOptions};
+
+#[cfg(feature = "adhoc")]
+pub use crate::residue::{Residue, ALTLOC_KEY};
+
+#[cfg(feature = "adhoc")]
+pub use crate::selection::Selection;
+
+#[cfg(feature = "adhoc")]
+pub use crate::spacegroup::{SymmetryOperation, ASYMMETRIC_SITE_KEY, SITE_MULTIPLICITY_KEY};
+
+#[cfg(feature = "adhoc")]
+pub use crate::store::FrameStore;
+
+#[cfg(feature = "adhoc")]
+pub use crate::substructure::SubstructureQuery;
+
+#[cfg(feature = "adhoc")]
+pub use crate::superposition::{AlignReference, TrajectoryAlignment};
 // 3a22317c ends here
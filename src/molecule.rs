@@ -146,6 +146,9 @@ impl Molecule {
     /// exist.
     pub fn remove_atom(&mut self, a: usize) -> Option<Atom> {
         if let Some(n) = self.remove_atom_sn(a) {
+            // keep atom groups consistent
+            #[cfg(feature = "adhoc")]
+            self.groups.values_mut().for_each(|g| g.discard(n));
             self.graph.remove_node(n)
         } else {
             None
@@ -188,6 +191,8 @@ impl Molecule {
     pub fn clear(&mut self) {
         self.mapping.clear();
         self.graph.clear();
+        #[cfg(feature = "adhoc")]
+        self.groups.values_mut().for_each(|g| g.clear());
     }
 
     /// Iterate over atoms ordered by serial numbers.
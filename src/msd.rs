@@ -0,0 +1,240 @@
+// [[file:../gchemol-core.note::7eb46714][7eb46714]]
+//! Mean squared displacement and diffusion coefficients from `Trajectory`
+// 7eb46714 ends here
+
+// [[file:../gchemol-core.note::a1786913][a1786913]]
+use crate::common::*;
+use crate::trajectory::Trajectory;
+
+use std::collections::BTreeMap;
+use vecfx::Vector3f;
+// a1786913 ends here
+
+// [[file:../gchemol-core.note::5cd09fee][5cd09fee]]
+/// Options for mean squared displacement analysis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MsdOptions {
+    /// The maximum lag in number of frames. Defaults to half of the
+    /// frames if not set.
+    pub max_lag: Option<usize>,
+
+    /// Stride in frames between successive time origins.
+    pub origin_stride: usize,
+
+    /// Time between successive frames, used only if frames have no
+    /// simulation time.
+    pub timestep: f64,
+
+    /// The range of lags for linear fitting of diffusion coefficient, as
+    /// fractions of `max_lag`. The ballistic regime at short times and
+    /// the noisy tail are usually excluded.
+    pub fit_range: (f64, f64),
+}
+
+impl Default for MsdOptions {
+    fn default() -> Self {
+        Self {
+            max_lag: None,
+            origin_stride: 1,
+            timestep: 1.0,
+            fit_range: (0.2, 0.8),
+        }
+    }
+}
+
+/// Mean squared displacement as a function of lag time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Msd {
+    /// Lag times, starting from 0.
+    pub lag_times: Vec<f64>,
+
+    /// Mean squared displacements at each lag time.
+    pub values: Vec<f64>,
+
+    /// Einstein diffusion coefficient, MSD = 6 D t, in units of length
+    /// squared over time. None if there are not enough points to fit.
+    pub diffusion_coefficient: Option<f64>,
+}
+
+/// Positions of atoms `indices` (0-based, in serial number order) in
+/// each frame, unwrapped across periodic boundaries by applying the
+/// minimum image convention to displacements between successive
+/// frames with the lattice of each frame.
+pub(crate) fn unwrapped_positions(traj: &Trajectory, indices: &[usize]) -> Vec<Vec<Vector3f>> {
+    let frames = &traj.frames;
+    let mut unwrapped: Vec<Vec<Vector3f>> = Vec::with_capacity(frames.len());
+    if let Some(first) = frames.first() {
+        unwrapped.push(indices.iter().map(|&i| first.positions()[i].into()).collect());
+    }
+    for pair in frames.windows(2) {
+        let (prev, curr) = (pair[0].positions(), pair[1].positions());
+        let last = unwrapped.last().unwrap();
+        let next = indices
+            .iter()
+            .zip(last)
+            .map(|(&i, &r)| {
+                let d = Vector3f::from(curr[i]) - Vector3f::from(prev[i]);
+                match pair[1].lattice() {
+                    Some(lat) => r + lat.apply_mic(d),
+                    None => r + d,
+                }
+            })
+            .collect();
+        unwrapped.push(next);
+    }
+    unwrapped
+}
+
+/// Least squares slope of `y` against `x`.
+fn fit_slope(x: &[f64], y: &[f64]) -> Option<f64> {
+    let n = x.len() as f64;
+    if x.len() < 2 {
+        return None;
+    }
+    let (mx, my) = (x.iter().sum::<f64>() / n, y.iter().sum::<f64>() / n);
+    let sxy: f64 = x.iter().zip(y).map(|(a, b)| (a - mx) * (b - my)).sum();
+    let sxx: f64 = x.iter().map(|a| (a - mx).powi(2)).sum();
+    (sxx > 0.0).then(|| sxy / sxx)
+}
+
+/// Mean squared displacement analysis
+impl Trajectory {
+    /// Return the mean squared displacement of atoms `atoms` (serial
+    /// numbers in the parent molecule), averaged over atoms and multiple
+    /// time origins, together with the fitted diffusion coefficient.
+    ///
+    /// # NOTE
+    /// * Positions are unwrapped across periodic boundaries using the
+    ///   lattice of each frame, which requires atoms not to move more
+    ///   than half of the cell between successive frames.
+    /// * Lag times are taken from simulation time of frames if all
+    ///   frames have it, or from `options.timestep` otherwise.
+    pub fn mean_squared_displacement(&self, atoms: &[usize], options: &MsdOptions) -> Result<Msd> {
+        ensure!(self.nframes() > 1, "not enough frames for MSD");
+        ensure!(!atoms.is_empty(), "no atoms for MSD");
+        ensure!(options.origin_stride > 0, "invalid origin stride");
+        let numbers: Vec<_> = self.parent().numbers().collect();
+        let indices: Vec<_> = atoms
+            .iter()
+            .map(|sn| numbers.iter().position(|x| x == sn).ok_or_else(|| format_err!("invalid atom sn: {sn}")))
+            .collect::<Result<_>>()?;
+
+        let nframes = self.nframes();
+        let max_lag = options.max_lag.unwrap_or(nframes / 2).min(nframes - 1);
+        let unwrapped = unwrapped_positions(self, &indices);
+        let values: Vec<f64> = (0..=max_lag)
+            .map(|lag| {
+                let origins = (0..nframes - lag).step_by(options.origin_stride);
+                let n = origins.len() * indices.len();
+                let sum: f64 = origins
+                    .flat_map(|t0| unwrapped[t0].iter().zip(&unwrapped[t0 + lag]).map(|(a, b)| (b - a).norm_squared()))
+                    .sum();
+                sum / n as f64
+            })
+            .collect();
+
+        let times: Option<Vec<f64>> = self.iter().map(|frame| frame.time).collect();
+        let lag_times: Vec<f64> = match times {
+            Some(t) => (0..=max_lag).map(|lag| t[lag] - t[0]).collect(),
+            None => (0..=max_lag).map(|lag| lag as f64 * options.timestep).collect(),
+        };
+
+        let (a, b) = options.fit_range;
+        let start = (a * max_lag as f64).round() as usize;
+        let end = ((b * max_lag as f64).round() as usize).min(max_lag);
+        let diffusion_coefficient = if start < end {
+            fit_slope(&lag_times[start..=end], &values[start..=end]).map(|slope| slope / 6.0)
+        } else {
+            None
+        };
+
+        Ok(Msd {
+            lag_times,
+            values,
+            diffusion_coefficient,
+        })
+    }
+
+    /// Return the mean squared displacement for atoms of each element.
+    pub fn msd_by_element(&self, options: &MsdOptions) -> Result<BTreeMap<String, Msd>> {
+        let mut elements: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (sn, atom) in self.parent().atoms() {
+            elements.entry(atom.symbol().to_owned()).or_default().push(sn);
+        }
+        elements
+            .into_iter()
+            .map(|(symbol, atoms)| Ok((symbol, self.mean_squared_displacement(&atoms, options)?)))
+            .collect()
+    }
+
+    /// Return the mean squared displacement for atoms in group
+    /// `group_name` defined in the parent molecule.
+    pub fn msd_by_group(&self, group_name: &str, options: &MsdOptions) -> Result<Msd> {
+        let parent = self.parent();
+        let group = parent.get_group(group_name).ok_or_else(|| format_err!("no such group: {group_name:?}"))?;
+        let atoms: Vec<_> = group.serial_numbers(parent).collect();
+        self.mean_squared_displacement(&atoms, options)
+    }
+}
+// 5cd09fee ends here
+
+// [[file:../gchemol-core.note::b18261b4][b18261b4]]
+#[test]
+fn test_msd() {
+    use crate::{Atom, Lattice, Molecule};
+    use std::convert::TryFrom;
+    use vecfx::approx::assert_relative_eq;
+
+    // 16 atoms walking along x with all sequences of four unit steps,
+    // so MSD(lag) = lag exactly, wrapped in a small periodic box; and a
+    // static Li atom.
+    let walks: Vec<Vec<f64>> = (0..16)
+        .map(|k| (0..4).map(|s| if k & (1 << s) == 0 { 1.0 } else { -1.0 }).collect())
+        .collect();
+    let lat = Lattice::from_params(3.0, 3.0, 3.0, 90.0, 90.0, 90.0);
+    let mols: Vec<_> = (0..5)
+        .map(|t| {
+            let mut atoms: Vec<_> = walks
+                .iter()
+                .map(|w| {
+                    let x: f64 = w[..t].iter().sum::<f64>() + 1.0;
+                    Atom::new("Ar", [x.rem_euclid(3.0), 0.0, 0.0])
+                })
+                .collect();
+            atoms.push(Atom::new("Li", [1.5, 1.5, 1.5]));
+            let mut mol = Molecule::from_atoms(atoms);
+            mol.set_lattice(lat);
+            mol
+        })
+        .collect();
+    let mut traj = Trajectory::try_from(mols).unwrap();
+
+    let options = MsdOptions {
+        max_lag: Some(4),
+        fit_range: (0.0, 1.0),
+        ..Default::default()
+    };
+    let ar: Vec<_> = (1..=16).collect();
+    let msd = traj.mean_squared_displacement(&ar, &options).unwrap();
+    assert_eq!(msd.lag_times, vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+    for (lag, &v) in msd.values.iter().enumerate() {
+        assert_relative_eq!(v, lag as f64, epsilon = 1e-8);
+    }
+    assert_relative_eq!(msd.diffusion_coefficient.unwrap(), 1.0 / 6.0, epsilon = 1e-8);
+
+    let by_element = traj.msd_by_element(&options).unwrap();
+    assert_eq!(by_element.len(), 2);
+    assert_relative_eq!(by_element["Li"].values[4], 0.0, epsilon = 1e-8);
+    assert_relative_eq!(by_element["Ar"].values[2], 2.0, epsilon = 1e-8);
+
+    // frame times are used if available
+    for (i, conf) in traj.frames.iter_mut().enumerate() {
+        conf.time = Some(i as f64 * 0.5);
+    }
+    let msd = traj.mean_squared_displacement(&ar[..1], &MsdOptions::default()).unwrap();
+    assert_eq!(msd.lag_times, vec![0.0, 0.5, 1.0]);
+    assert!(traj.msd_by_group("ions", &options).is_err());
+    assert!(traj.mean_squared_displacement(&[99], &options).is_err());
+}
+// b18261b4 ends here
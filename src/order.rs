@@ -2,7 +2,9 @@
 
 // [[file:../gchemol-core.note::*imports][imports:1]]
 use crate::common::*;
-use crate::Molecule;
+use crate::{Atom, Molecule};
+
+use gchemol_graph::petgraph::visit::EdgeRef;
 // imports:1 ends here
 
 // [[file:../gchemol-core.note::*core][core:1]]
@@ -84,6 +86,168 @@ impl Molecule {
 }
 // 31e7954d ends here
 
+// [[file:../gchemol-core.note::5ba3194e][5ba3194e]]
+use crate::isomorphism::{bond_label, rank_labels, refine_colors};
+use std::collections::BTreeMap;
+
+/// Bonds in terms of atom labels: (label1, label2, bond kind)
+type Certificate = Vec<(usize, usize, usize)>;
+
+/// Search for the canonical labeling by individualization and
+/// refinement. Among all labelings reached by breaking ties, the one
+/// with the smallest bond list is chosen; branches equivalent under
+/// automorphisms found on the way are pruned.
+struct CanonicalSearch<'a> {
+    neighbors: &'a [Vec<(usize, usize)>],
+    /// the best certificate and its labeling
+    best: Option<(Certificate, Vec<usize>)>,
+    /// automorphisms discovered from leaves with equal certificates
+    automorphisms: Vec<Vec<usize>>,
+}
+
+impl<'a> CanonicalSearch<'a> {
+    fn new(neighbors: &'a [Vec<(usize, usize)>]) -> Self {
+        Self {
+            neighbors,
+            best: None,
+            automorphisms: vec![],
+        }
+    }
+
+    /// Give atom `v` a unique color ahead of its cell, and refine.
+    fn individualize(&self, colors: &[usize], v: usize) -> Vec<usize> {
+        let labels: Vec<_> = colors.iter().enumerate().map(|(i, &c)| (c, i != v)).collect();
+        refine_colors(self.neighbors, rank_labels(&labels))
+    }
+
+    fn certificate(&self, labels: &[usize]) -> Certificate {
+        let mut cert = vec![];
+        for (i, nn) in self.neighbors.iter().enumerate() {
+            for &(j, b) in nn {
+                if labels[i] < labels[j] {
+                    cert.push((labels[i], labels[j], b));
+                }
+            }
+        }
+        cert.sort_unstable();
+        cert
+    }
+
+    /// Orbits of atoms under automorphisms that fix atoms in `fixed`.
+    fn orbits(&self, fixed: &[usize]) -> Vec<usize> {
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+
+        let mut parent: Vec<_> = (0..self.neighbors.len()).collect();
+        for g in self.automorphisms.iter().filter(|g| fixed.iter().all(|&x| g[x] == x)) {
+            for (a, &b) in g.iter().enumerate() {
+                let (ra, rb) = (root(&mut parent, a), root(&mut parent, b));
+                parent[ra] = rb;
+            }
+        }
+        (0..parent.len()).map(|i| root(&mut parent, i)).collect()
+    }
+
+    fn search(&mut self, colors: Vec<usize>, fixed: &mut Vec<usize>) {
+        let mut cells: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (i, &c) in colors.iter().enumerate() {
+            cells.entry(c).or_default().push(i);
+        }
+        // the first smallest cell with ties
+        let cell = cells.into_values().filter(|c| c.len() > 1).min_by_key(|c| c.len());
+
+        if let Some(cell) = cell {
+            let mut tried: Vec<usize> = vec![];
+            for v in cell {
+                let orbits = self.orbits(fixed);
+                if tried.iter().any(|&u| orbits[u] == orbits[v]) {
+                    continue;
+                }
+                fixed.push(v);
+                let refined = self.individualize(&colors, v);
+                self.search(refined, fixed);
+                fixed.pop();
+                tried.push(v);
+            }
+        } else {
+            let cert = self.certificate(&colors);
+            match &self.best {
+                Some((best_cert, best_labels)) if cert == *best_cert => {
+                    let mut inv = vec![0; best_labels.len()];
+                    for (i, &l) in best_labels.iter().enumerate() {
+                        inv[l] = i;
+                    }
+                    let g = colors.iter().map(|&l| inv[l]).collect();
+                    self.automorphisms.push(g);
+                }
+                Some((best_cert, _)) if cert > *best_cert => {}
+                _ => self.best = Some((cert, colors)),
+            }
+        }
+    }
+}
+
+/// Return the canonical label (from 0) for each atom in serial number
+/// order, with atoms distinguished by `invariant`.
+pub(crate) fn canonical_labels_by<K: Ord>(mol: &Molecule, invariant: impl Fn(&Atom) -> K) -> Vec<usize> {
+    let (graph, _) = mol.compact_graph();
+    let invariants: Vec<_> = graph.node_weights().map(|&a| invariant(a)).collect();
+
+    let mut neighbors = vec![vec![]; graph.node_count()];
+    for e in graph.edge_references() {
+        let (i, j, b) = (e.source().index(), e.target().index(), bond_label(e.weight().kind()));
+        neighbors[i].push((j, b));
+        neighbors[j].push((i, b));
+    }
+
+    let colors = refine_colors(&neighbors, rank_labels(&invariants));
+    let mut search = CanonicalSearch::new(&neighbors);
+    search.search(colors, &mut vec![]);
+    search.best.map(|(_, labels)| labels).unwrap_or_default()
+}
+
+/// Return the canonical label (from 0) for each atom in serial number
+/// order. Heavy atoms come first; ties are broken by charge and
+/// isotope.
+fn canonical_labels(mol: &Molecule) -> Vec<usize> {
+    let key = |x: Option<f64>| (x.unwrap_or(0.0) * 1000.0).round() as i64;
+    canonical_labels_by(mol, |a| {
+        let invariant = (std::cmp::Reverse(a.number()), key(a.partial_charge), key(a.get_mass()));
+        (invariant, a.symbol().to_owned())
+    })
+}
+
+/// Canonical ordering of `Atom` in `Molecule`
+impl Molecule {
+    /// Return atom serial numbers in canonical order. The order depends
+    /// only on the bonding graph, with atoms labelled by element,
+    /// partial charge and mass (isotope), and bonds by bond kind, so
+    /// any permutation of the same structure gives the same order of
+    /// atoms up to topological symmetry.
+    pub fn canonical_order(&self) -> Vec<usize> {
+        let numbers: Vec<_> = self.numbers().collect();
+        let labels = canonical_labels(self);
+        let mut order = vec![0; numbers.len()];
+        for (&sn, &l) in numbers.iter().zip(labels.iter()) {
+            order[l] = sn;
+        }
+        order
+    }
+
+    /// Renumber atoms from 1 in canonical order. See also
+    /// [canonical_order](#method.canonical_order).
+    pub fn canonicalize(&mut self) {
+        let labels = canonical_labels(self);
+        self.reorder(&labels);
+    }
+}
+// 5ba3194e ends here
+
 // [[file:../gchemol-core.note::*test][test:1]]
 #[test]
 fn test_atom_orders() {
@@ -123,3 +287,64 @@ fn test_atom_orders() {
     assert_eq!(d, expected);
 }
 // test:1 ends here
+
+// [[file:../gchemol-core.note::2071fa74][2071fa74]]
+#[test]
+fn test_canonical_order() {
+    use crate::{Atom, Bond};
+
+    // acetaldehyde: C1 C2 O3 H4..H7
+    let atoms = [
+        ("C", [0.0, 0.0, 0.0]),
+        ("C", [1.5, 0.0, 0.0]),
+        ("O", [2.1, 1.1, 0.0]),
+        ("H", [-0.4, 1.0, 0.0]),
+        ("H", [-0.4, -0.5, 0.9]),
+        ("H", [-0.4, -0.5, -0.9]),
+        ("H", [2.0, -1.0, 0.0]),
+    ];
+    let mut mol = Molecule::from_atoms(atoms.iter().map(|&(s, p)| Atom::new(s, p)));
+    mol.add_bond(1, 2, Bond::single());
+    mol.add_bond(2, 3, Bond::double());
+    mol.add_bond(2, 7, Bond::single());
+    for h in 4..=6 {
+        mol.add_bond(1, h, Bond::single());
+    }
+
+    let labelled = |m: &Molecule| -> (Vec<String>, Vec<(usize, usize, f64)>) {
+        let symbols = m.symbols().map(|s| s.to_string()).collect();
+        let bonds = m
+            .bonds()
+            .map(|(i, j, b)| (i.min(j), i.max(j), b.order()))
+            .sorted_by(|a, b| a.partial_cmp(b).unwrap())
+            .collect();
+        (symbols, bonds)
+    };
+    let mut mol1 = mol.clone();
+    mol1.canonicalize();
+    assert_eq!(mol1.symbols().collect_vec(), ["O", "C", "C", "H", "H", "H", "H"]);
+
+    // any permutation leads to the same canonical structure
+    for keys in [[7, 6, 5, 4, 3, 2, 1], [3, 1, 4, 7, 5, 2, 6], [2, 7, 1, 5, 6, 3, 4]] {
+        let mut mol2 = mol.clone();
+        mol2.reorder(&keys);
+        assert_eq!(mol2.canonical_order().len(), 7);
+        mol2.canonicalize();
+        assert_eq!(labelled(&mol1), labelled(&mol2));
+    }
+
+    // ties in a symmetric ring are broken consistently
+    let mut ring = Molecule::from_atoms((0..6).map(|i| Atom::new(if i % 2 == 0 { "C" } else { "N" }, [i as f64, 0.0, 0.0])));
+    for i in 1..=6 {
+        ring.add_bond(i, i % 6 + 1, Bond::single());
+    }
+    let order = ring.canonical_order();
+    assert_eq!(order.iter().copied().sorted().collect_vec(), (1..=6).collect_vec());
+    let mut ring1 = ring.clone();
+    ring1.canonicalize();
+    let mut ring2 = ring.clone();
+    ring2.reorder(&[4, 2, 6, 1, 5, 3]);
+    ring2.canonicalize();
+    assert_eq!(labelled(&ring1), labelled(&ring2));
+}
+// 2071fa74 ends here
@@ -0,0 +1,572 @@
+// [[file:../gchemol-core.note::f85fa4e9][f85fa4e9]]
+//! Point group detection and symmetrization for non-periodic molecules.
+//!
+//! Symmetry operations are searched along candidate axes defined by
+//! principal axes, atom positions and pairs of equivalent atoms. The
+//! found operations are then closed under multiplication to give the
+//! full point group.
+// f85fa4e9 ends here
+
+// [[file:../gchemol-core.note::1efec2c5][1efec2c5]]
+use crate::common::*;
+use crate::{Molecule, Point3};
+
+use vecfx::nalgebra as na;
+use vecfx::{Matrix3f, Vector3f};
+
+use std::f64::consts::PI;
+// 1efec2c5 ends here
+
+// [[file:../gchemol-core.note::219dd51c][219dd51c]]
+/// Symmetry element of a molecular point group.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum SymmetryElement {
+    /// The identity E
+    Identity,
+    /// The inversion center i
+    Inversion,
+    /// The proper rotation axis C_n
+    Rotation {
+        /// unit vector of the rotation axis
+        axis: Point3,
+        /// the highest order n of the axis. Zero for C∞ axis.
+        order: usize,
+    },
+    /// The mirror plane σ
+    Reflection {
+        /// unit normal vector of the mirror plane
+        normal: Point3,
+    },
+    /// The improper rotation axis S_n
+    ImproperRotation {
+        /// unit vector of the rotation axis
+        axis: Point3,
+        /// the highest order n of the axis
+        order: usize,
+    },
+}
+
+/// The point group of a molecule.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PointGroup {
+    symbol: String,
+    elements: Vec<SymmetryElement>,
+    equivalent_atoms: Vec<Vec<usize>>,
+
+    /// All symmetry operations in reference to center of mass.
+    #[serde(skip)]
+    operations: Vec<Matrix3f>,
+
+    /// The molecular axis for linear molecule
+    #[serde(skip)]
+    linear_axis: Option<Vector3f>,
+}
+
+impl PointGroup {
+    /// Return the Schoenflies symbol of the point group, such as "C2v",
+    /// "D6h", "Td" or "D∞h".
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// Return the symmetry elements in the point group.
+    pub fn elements(&self) -> &[SymmetryElement] {
+        &self.elements
+    }
+
+    /// Return classes of symmetry-equivalent atoms in serial numbers.
+    pub fn equivalent_atoms(&self) -> &[Vec<usize>] {
+        &self.equivalent_atoms
+    }
+}
+// 219dd51c ends here
+
+// [[file:../gchemol-core.note::747793a2][747793a2]]
+/// The tolerance in matrix elements for treating two symmetry operations as
+/// the same.
+const OPERATION_TOLERANCE: f64 = 0.15;
+
+/// The maximum number of symmetry operations, as in Ih group.
+const MAX_OPERATIONS: usize = 120;
+
+/// Centered atom positions with element types for symmetry analysis.
+struct Structure {
+    positions: Vec<Vector3f>,
+    numbers: Vec<usize>,
+    tolerance: f64,
+}
+
+impl Structure {
+    fn from_molecule(mol: &Molecule, tolerance: f64) -> Self {
+        let com: Vector3f = mol.center_of_mass().into();
+        Self {
+            positions: mol.positions().map(|p| Vector3f::from(p) - com).collect(),
+            numbers: mol.atomic_numbers().collect(),
+            tolerance,
+        }
+    }
+
+    /// Return the atom permutation induced by symmetry operation `op`.
+    /// Return None if `op` is not a symmetry operation of the structure.
+    fn permutation(&self, op: &Matrix3f) -> Option<Vec<usize>> {
+        let mut perm = Vec::with_capacity(self.positions.len());
+        for (p, n) in self.positions.iter().zip(&self.numbers) {
+            let q = op * p;
+            let j = self
+                .positions
+                .iter()
+                .zip(&self.numbers)
+                .position(|(pj, nj)| nj == n && (pj - q).norm() < self.tolerance)?;
+            perm.push(j);
+        }
+        Some(perm)
+    }
+
+    fn is_symmetry_operation(&self, op: &Matrix3f) -> bool {
+        self.permutation(op).is_some()
+    }
+
+    /// Return true if all atoms are lying on the line along `axis`.
+    fn is_linear(&self, axis: &Vector3f) -> bool {
+        self.positions.iter().all(|p| p.cross(axis).norm() < self.tolerance)
+    }
+
+    /// Candidate symmetry axes defined by atom positions and pairs of
+    /// possibly equivalent atoms.
+    fn candidate_axes(&self, principal_axes: &[Vector3f]) -> Vec<Vector3f> {
+        let mut axes: Vec<Vector3f> = principal_axes.to_vec();
+        let n = self.positions.len();
+        for i in 0..n {
+            let pi = self.positions[i];
+            axes.push(pi);
+            for j in i + 1..n {
+                let pj = self.positions[j];
+                if self.numbers[i] == self.numbers[j] && (pi.norm() - pj.norm()).abs() < self.tolerance {
+                    axes.push(pi + pj);
+                    axes.push(pi - pj);
+                    axes.push(pi.cross(&pj));
+                }
+            }
+        }
+
+        let mut unique: Vec<Vector3f> = vec![];
+        for v in axes {
+            let norm = v.norm();
+            if norm < self.tolerance {
+                continue;
+            }
+            let v = v / norm;
+            if !unique.iter().any(|u| u.dot(&v).abs() > 0.9999) {
+                unique.push(v);
+            }
+        }
+        unique
+    }
+}
+
+fn rotation_matrix(axis: &Vector3f, angle: f64) -> Matrix3f {
+    let axis = na::Unit::new_normalize(*axis);
+    na::Rotation3::from_axis_angle(&axis, angle).into_inner()
+}
+
+fn reflection_matrix(normal: &Vector3f) -> Matrix3f {
+    let n = normal.normalize();
+    Matrix3f::identity() - 2.0 * n * n.transpose()
+}
+
+fn contains_operation(ops: &[Matrix3f], op: &Matrix3f) -> bool {
+    ops.iter().any(|o| (o - op).abs().max() < OPERATION_TOLERANCE)
+}
+
+/// Search symmetry operations of `structure` and close them under
+/// multiplication.
+fn find_operations(structure: &Structure, axes: &[Vector3f]) -> Vec<Matrix3f> {
+    let mut ops = vec![Matrix3f::identity()];
+    let add = |ops: &mut Vec<Matrix3f>, op: Matrix3f| {
+        if !contains_operation(ops, &op) && structure.is_symmetry_operation(&op) {
+            ops.push(op);
+        }
+    };
+
+    add(&mut ops, -Matrix3f::identity());
+    for axis in axes {
+        for n in 2..=6 {
+            add(&mut ops, rotation_matrix(axis, 2.0 * PI / n as f64));
+        }
+        let sigma = reflection_matrix(axis);
+        add(&mut ops, sigma);
+        for n in [4, 8] {
+            add(&mut ops, sigma * rotation_matrix(axis, 2.0 * PI / n as f64));
+        }
+    }
+
+    // group closure
+    loop {
+        let mut found = vec![];
+        for a in ops.iter() {
+            for b in ops.iter() {
+                let c = a * b;
+                if !contains_operation(&ops, &c) && !contains_operation(&found, &c) && structure.is_symmetry_operation(&c) {
+                    found.push(c);
+                }
+            }
+        }
+        if found.is_empty() || ops.len() + found.len() > MAX_OPERATIONS {
+            ops.extend(found);
+            break;
+        }
+        ops.extend(found);
+    }
+
+    ops
+}
+// 747793a2 ends here
+
+// [[file:../gchemol-core.note::85e070da][85e070da]]
+/// Return the order n of rotation by `angle` in radians as a power of
+/// C_n.
+fn rotation_order(angle: f64) -> usize {
+    let f = angle / (2.0 * PI);
+    (1..=12).find(|&d| (f * d as f64 - (f * d as f64).round()).abs() < 0.02).unwrap_or(0)
+}
+
+/// Return the rotation axis and angle of proper rotation matrix `r`.
+fn rotation_axis_angle(r: &Matrix3f) -> (Vector3f, f64) {
+    let cos = ((r.trace() - 1.0) / 2.0).clamp(-1.0, 1.0);
+    let angle = cos.acos();
+    let v = Vector3f::new(r[(2, 1)] - r[(1, 2)], r[(0, 2)] - r[(2, 0)], r[(1, 0)] - r[(0, 1)]);
+    let axis = if v.norm() > 1e-3 {
+        v.normalize()
+    } else {
+        // rotation by π: R + I = 2 n n^T
+        let m = r + Matrix3f::identity();
+        let i = (0..3).max_by(|&i, &j| m.column(i).norm().total_cmp(&m.column(j).norm())).unwrap();
+        m.column(i).normalize()
+    };
+    (axis, angle)
+}
+
+/// Merge parallel axes, keeping the highest order.
+fn merge_axis(axes: &mut Vec<(Vector3f, usize)>, axis: Vector3f, order: usize) {
+    if let Some(found) = axes.iter_mut().find(|(a, _)| a.dot(&axis).abs() > 0.99) {
+        found.1 = found.1.max(order);
+    } else {
+        axes.push((axis, order));
+    }
+}
+
+#[derive(Debug, Default)]
+struct Elements {
+    inversion: bool,
+    proper: Vec<(Vector3f, usize)>,
+    improper: Vec<(Vector3f, usize)>,
+    mirrors: Vec<Vector3f>,
+}
+
+impl Elements {
+    fn from_operations(ops: &[Matrix3f]) -> Self {
+        let mut elements = Self::default();
+        for op in ops {
+            if op.determinant() > 0.0 {
+                let (axis, angle) = rotation_axis_angle(op);
+                let n = rotation_order(angle);
+                if n > 1 {
+                    merge_axis(&mut elements.proper, axis, n);
+                }
+            } else {
+                let m = -op;
+                let (axis, angle) = rotation_axis_angle(&m);
+                if angle < 0.1 {
+                    elements.inversion = true;
+                } else {
+                    let n = rotation_order(angle + PI);
+                    if n == 1 {
+                        if !elements.mirrors.iter().any(|a| a.dot(&axis).abs() > 0.99) {
+                            elements.mirrors.push(axis);
+                        }
+                    } else if n > 2 {
+                        merge_axis(&mut elements.improper, axis, n);
+                    }
+                }
+            }
+        }
+        elements
+    }
+
+    fn has_mirror_normal_to(&self, axis: &Vector3f) -> bool {
+        self.mirrors.iter().any(|m| m.dot(axis).abs() > 0.99)
+    }
+
+    /// Return the Schoenflies symbol.
+    fn schoenflies_symbol(&self) -> String {
+        let inv = self.inversion;
+        let has_mirror = !self.mirrors.is_empty();
+
+        // cubic groups have more than one high order axis
+        let high_order = self.proper.iter().filter(|(_, n)| *n >= 3).count();
+        if high_order > 1 {
+            let nmax = self.proper.iter().map(|(_, n)| *n).max().unwrap_or(1);
+            let symbol = match (nmax, inv, has_mirror) {
+                (5, true, _) => "Ih",
+                (5, false, _) => "I",
+                (4, true, _) => "Oh",
+                (4, false, _) => "O",
+                (_, true, _) => "Th",
+                (_, false, true) => "Td",
+                _ => "T",
+            };
+            return symbol.into();
+        }
+
+        let nmax = self.proper.iter().map(|(_, n)| *n).max().unwrap_or(1);
+        if nmax == 1 {
+            return if has_mirror {
+                "Cs"
+            } else if inv {
+                "Ci"
+            } else {
+                "C1"
+            }
+            .into();
+        }
+
+        // choose principal axis, preferring the one coinciding with S2n axis
+        let candidates = self.proper.iter().filter(|(_, n)| *n == nmax).map(|(a, _)| a).collect_vec();
+        let has_s2n = |axis: &Vector3f| {
+            self.improper
+                .iter()
+                .any(|(a, m)| *m == 2 * nmax && a.dot(axis).abs() > 0.99)
+        };
+        let principal = candidates.iter().find(|a| has_s2n(a)).unwrap_or(&candidates[0]);
+        let perpendicular_c2 = self
+            .proper
+            .iter()
+            .any(|(a, n)| *n >= 2 && a.dot(principal).abs() < 0.01);
+        let sigma_h = self.has_mirror_normal_to(principal);
+
+        if perpendicular_c2 {
+            if sigma_h {
+                format!("D{nmax}h")
+            } else if has_mirror {
+                format!("D{nmax}d")
+            } else {
+                format!("D{nmax}")
+            }
+        } else if sigma_h {
+            format!("C{nmax}h")
+        } else if has_mirror {
+            format!("C{nmax}v")
+        } else if has_s2n(principal) {
+            format!("S{}", 2 * nmax)
+        } else {
+            format!("C{nmax}")
+        }
+    }
+
+    fn to_symmetry_elements(&self) -> Vec<SymmetryElement> {
+        let mut elements = vec![SymmetryElement::Identity];
+        if self.inversion {
+            elements.push(SymmetryElement::Inversion);
+        }
+        for (axis, order) in self.proper.iter() {
+            let axis = (*axis).into();
+            elements.push(SymmetryElement::Rotation { axis, order: *order });
+        }
+        for normal in self.mirrors.iter() {
+            let normal = (*normal).into();
+            elements.push(SymmetryElement::Reflection { normal });
+        }
+        for (axis, order) in self.improper.iter() {
+            let axis = (*axis).into();
+            elements.push(SymmetryElement::ImproperRotation { axis, order: *order });
+        }
+        elements
+    }
+}
+
+/// Group atoms into equivalent classes by permutations of symmetry operations.
+fn equivalent_atoms(structure: &Structure, ops: &[Matrix3f], numbers: &[usize]) -> Vec<Vec<usize>> {
+    let n = numbers.len();
+    let mut class: Vec<_> = (0..n).collect();
+    for op in ops {
+        if let Some(perm) = structure.permutation(op) {
+            for (i, j) in perm.into_iter().enumerate() {
+                let (ci, cj) = (class[i], class[j]);
+                if ci != cj {
+                    let (keep, drop) = (ci.min(cj), ci.max(cj));
+                    class.iter_mut().filter(|c| **c == drop).for_each(|c| *c = keep);
+                }
+            }
+        }
+    }
+
+    (0..n)
+        .into_group_map_by(|&i| class[i])
+        .into_iter()
+        .sorted_by_key(|(c, _)| *c)
+        .map(|(_, members)| members.into_iter().map(|i| numbers[i]).collect())
+        .collect()
+}
+
+fn find_point_group(mol: &Molecule, tolerance: f64) -> PointGroup {
+    let structure = Structure::from_molecule(mol, tolerance);
+    let numbers = mol.numbers().collect_vec();
+
+    let axes = mol.principal_axes().map(Vector3f::from);
+    // the molecular axis has the smallest moment of inertia
+    if mol.natoms() == 1 || structure.is_linear(&axes[0]) {
+        let mut ops = vec![Matrix3f::identity()];
+        let inversion = -Matrix3f::identity();
+        let (symbol, elements) = if mol.natoms() > 1 && structure.is_symmetry_operation(&inversion) {
+            ops.push(inversion);
+            let axis = axes[0].into();
+            let elements = vec![
+                SymmetryElement::Identity,
+                SymmetryElement::Inversion,
+                SymmetryElement::Rotation { axis, order: 0 },
+                SymmetryElement::Reflection { normal: axis },
+            ];
+            ("D∞h", elements)
+        } else if mol.natoms() > 1 {
+            let axis = axes[0].into();
+            let elements = vec![SymmetryElement::Identity, SymmetryElement::Rotation { axis, order: 0 }];
+            ("C∞v", elements)
+        } else {
+            ("Kh", vec![SymmetryElement::Identity, SymmetryElement::Inversion])
+        };
+        let equivalent_atoms = equivalent_atoms(&structure, &ops, &numbers);
+        return PointGroup {
+            symbol: symbol.into(),
+            elements,
+            equivalent_atoms,
+            operations: ops,
+            linear_axis: Some(axes[0]),
+        };
+    }
+
+    let candidates = structure.candidate_axes(&axes);
+    let ops = find_operations(&structure, &candidates);
+    let elements = Elements::from_operations(&ops);
+    PointGroup {
+        symbol: elements.schoenflies_symbol(),
+        elements: elements.to_symmetry_elements(),
+        equivalent_atoms: equivalent_atoms(&structure, &ops, &numbers),
+        operations: ops,
+        linear_axis: None,
+    }
+}
+// 85e070da ends here
+
+// [[file:../gchemol-core.note::53855ac5][53855ac5]]
+/// Point group related methods
+impl Molecule {
+    /// Detect the point group of the molecule. Return None if the
+    /// molecule is periodic or has no atoms, or if `tolerance` is not
+    /// positive.
+    ///
+    /// # Parameters
+    /// * tolerance: the distance tolerance in Å for matching
+    ///   symmetry-equivalent atoms, such as 0.1.
+    pub fn point_group(&self, tolerance: f64) -> Option<PointGroup> {
+        if tolerance.is_nan() || tolerance <= 0.0 || self.is_periodic() || self.natoms() == 0 {
+            return None;
+        }
+        find_point_group(self, tolerance).into()
+    }
+
+    /// Symmetrize the molecule by snapping positions of atoms onto the
+    /// exact point group detected with `tolerance`. The center of mass
+    /// is kept unchanged. Return the point group on success.
+    pub fn symmetrize(&mut self, tolerance: f64) -> Option<PointGroup> {
+        // the symmetry operations are refined with the geometry
+        for _ in 0..20 {
+            let pg = self.point_group(tolerance)?;
+            let structure = Structure::from_molecule(self, tolerance);
+            let com: Vector3f = self.center_of_mass().into();
+
+            let mut positions = vec![Vector3f::zeros(); structure.positions.len()];
+            let mut nops = 0;
+            for op in pg.operations.iter() {
+                // skip the ops become invalid after the refinement
+                let Some(perm) = structure.permutation(op) else { continue };
+                let op_inv = op.transpose();
+                for (i, j) in perm.into_iter().enumerate() {
+                    positions[i] += op_inv * structure.positions[j];
+                }
+                nops += 1;
+            }
+            for p in positions.iter_mut() {
+                *p /= nops as f64;
+                if let Some(axis) = pg.linear_axis {
+                    *p = p.dot(&axis) * axis;
+                }
+            }
+
+            let disp = positions
+                .iter()
+                .zip(&structure.positions)
+                .map(|(p, q)| (p - q).norm())
+                .fold(0.0, f64::max);
+            self.set_positions(positions.into_iter().map(|p| p + com));
+            if disp < 1e-10 {
+                break;
+            }
+        }
+        self.point_group(tolerance)
+    }
+}
+// 53855ac5 ends here
+
+// [[file:../gchemol-core.note::9779dbdd][9779dbdd]]
+#[test]
+fn test_point_group() {
+    use crate::Atom;
+
+    let mol = Molecule::from_database("CH4");
+    let pg = mol.point_group(0.01).unwrap();
+    assert_eq!(pg.symbol(), "Td");
+    assert_eq!(pg.equivalent_atoms(), &[vec![1], vec![2, 3, 4, 5]]);
+    let nc3 = pg
+        .elements()
+        .iter()
+        .filter(|e| matches!(e, SymmetryElement::Rotation { order: 3, .. }))
+        .count();
+    assert_eq!(nc3, 4);
+
+    let mol = Molecule::from_database("H2O");
+    assert_eq!(mol.point_group(0.01).unwrap().symbol(), "C2v");
+    let mol = Molecule::from_database("HCN");
+    assert_eq!(mol.point_group(0.01).unwrap().symbol(), "C∞v");
+
+    // benzene
+    let mut atoms = vec![];
+    for (sym, r) in [("C", 1.39), ("H", 2.47)] {
+        for i in 0..6 {
+            let a = (i as f64 * 60.0).to_radians();
+            atoms.push(Atom::new(sym, [r * a.cos(), r * a.sin(), 0.0]));
+        }
+    }
+    let mut mol = Molecule::from_atoms(atoms);
+    let pg = mol.point_group(0.01).unwrap();
+    assert_eq!(pg.symbol(), "D6h");
+    assert_eq!(pg.equivalent_atoms().len(), 2);
+
+    // symmetrize a distorted benzene
+    mol.set_position(1, [1.40, 0.01, 0.0]);
+    mol.set_position(8, [1.24, 2.14, 0.02]);
+    assert_eq!(mol.point_group(0.001).unwrap().symbol(), "C1");
+    let pg = mol.symmetrize(0.05).unwrap();
+    assert_eq!(pg.symbol(), "D6h");
+    assert_eq!(mol.point_group(1e-6).unwrap().symbol(), "D6h");
+
+    // invalid tolerance
+    assert!(mol.point_group(0.0).is_none());
+    assert!(mol.point_group(-0.1).is_none());
+    assert!(mol.point_group(f64::NAN).is_none());
+    assert!(mol.symmetrize(-0.1).is_none());
+
+    // periodic structure
+    mol.set_lattice(crate::Lattice::default());
+    assert!(mol.point_group(0.01).is_none());
+}
+// 9779dbdd ends here
@@ -0,0 +1,241 @@
+// [[file:../gchemol-core.note::cfdf79e0][cfdf79e0]]
+//! Radial distribution function and coordination numbers
+// cfdf79e0 ends here
+
+// [[file:../gchemol-core.note::edf0098f][edf0098f]]
+use crate::common::*;
+use crate::selection::neighborhood_probe_from;
+use crate::trajectory::Trajectory;
+use crate::{Lattice, Molecule};
+
+use std::collections::HashSet;
+// edf0098f ends here
+
+// [[file:../gchemol-core.note::2ba369a1][2ba369a1]]
+/// Options for radial distribution function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RdfOptions {
+    /// The width of distance bins.
+    pub bin_width: f64,
+
+    /// The maximum distance.
+    pub cutoff: f64,
+
+    /// Element symbols of central and surrounding atoms, such as `("O",
+    /// "H")`. All atoms are considered if not set.
+    pub elements: Option<(String, String)>,
+}
+
+impl Default for RdfOptions {
+    fn default() -> Self {
+        Self {
+            bin_width: 0.05,
+            cutoff: 6.0,
+            elements: None,
+        }
+    }
+}
+
+/// Radial distribution function g(r) in distance bins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rdf {
+    /// Distances at bin centers.
+    pub r: Vec<f64>,
+
+    /// Radial distribution function at each bin.
+    pub g: Vec<f64>,
+
+    /// Running coordination numbers: average number of surrounding atoms
+    /// around a central atom up to the upper edge of each bin.
+    pub coordination_numbers: Vec<f64>,
+}
+
+/// Central and surrounding atoms selected by element pair.
+fn select_pair(mol: &Molecule, options: &RdfOptions) -> (Vec<usize>, HashSet<usize>) {
+    let select = |symbol: Option<&String>| {
+        mol.atoms()
+            .filter(|(_, a)| symbol.is_none_or(|s| a.symbol() == s))
+            .map(|(i, _)| i)
+            .collect_vec()
+    };
+    let (a, b) = match &options.elements {
+        Some((a, b)) => (Some(a), Some(b)),
+        None => (None, None),
+    };
+    (select(a), select(b).into_iter().collect())
+}
+
+/// Compute g(r) and running coordination numbers for `particles` in
+/// `lattice`, using neighbor search over all periodic images within
+/// cutoff.
+fn compute_rdf(
+    particles: Vec<(usize, [f64; 3])>,
+    lattice: &Lattice,
+    centers: &[usize],
+    targets: &HashSet<usize>,
+    options: &RdfOptions,
+) -> (Vec<f64>, Vec<f64>) {
+    let bw = options.bin_width;
+    let nbins = (options.cutoff / bw).ceil() as usize;
+    let probe = neighborhood_probe_from(particles, Some(lattice));
+    let mut hist = vec![0.0; nbins];
+    for &i in centers {
+        for n in probe.neighbors(i, options.cutoff).filter(|n| targets.contains(&n.node)) {
+            let k = (n.distance / bw) as usize;
+            if k < nbins {
+                hist[k] += 1.0;
+            }
+        }
+    }
+
+    // number of distinct pairs, excluding atoms paired with themselves
+    let na = centers.len() as f64;
+    let npairs: usize = centers.iter().map(|i| targets.len() - targets.contains(i) as usize).sum();
+    let rho = npairs as f64 / na / lattice.volume();
+    let g = hist
+        .iter()
+        .enumerate()
+        .map(|(k, h)| {
+            let (r1, r2) = (k as f64 * bw, (k + 1) as f64 * bw);
+            let shell = 4.0 / 3.0 * std::f64::consts::PI * (r2.powi(3) - r1.powi(3));
+            h / (na * rho * shell)
+        })
+        .collect();
+    let cn = hist
+        .iter()
+        .scan(0.0, |acc, h| {
+            *acc += h / na;
+            Some(*acc)
+        })
+        .collect();
+    (g, cn)
+}
+
+fn check_options(options: &RdfOptions) -> Result<()> {
+    ensure!(options.bin_width > 0.0, "invalid bin width: {}", options.bin_width);
+    ensure!(options.cutoff > 0.0, "invalid cutoff: {}", options.cutoff);
+    Ok(())
+}
+
+fn bin_centers(options: &RdfOptions) -> Vec<f64> {
+    let nbins = (options.cutoff / options.bin_width).ceil() as usize;
+    (0..nbins).map(|k| (k as f64 + 0.5) * options.bin_width).collect()
+}
+
+impl Molecule {
+    /// Return the radial distribution function g(r) of periodic
+    /// structure, together with running coordination numbers.
+    ///
+    /// # NOTE
+    /// * Neighbors are searched over all periodic images within cutoff,
+    ///   which reduces to the minimum image convention for cutoff less
+    ///   than half of the cell width.
+    /// * Return error if the structure is not periodic or no atoms are
+    ///   selected.
+    pub fn rdf(&self, options: &RdfOptions) -> Result<Rdf> {
+        check_options(options)?;
+        let lattice = self.lattice.ok_or_else(|| format_err!("rdf requires periodic lattice"))?;
+        let (centers, targets) = select_pair(self, options);
+        ensure!(!centers.is_empty() && !targets.is_empty(), "no atoms selected for rdf");
+        let particles = self.atoms().map(|(i, a)| (i, a.position())).collect();
+        let (g, coordination_numbers) = compute_rdf(particles, &lattice, &centers, &targets, options);
+        Ok(Rdf {
+            r: bin_centers(options),
+            g,
+            coordination_numbers,
+        })
+    }
+}
+
+impl Trajectory {
+    /// Return the radial distribution function g(r) and running
+    /// coordination numbers averaged over all frames. See
+    /// [Molecule::rdf](struct.Molecule.html#method.rdf) for details.
+    pub fn rdf(&self, options: &RdfOptions) -> Result<Rdf> {
+        check_options(options)?;
+        ensure!(!self.is_empty(), "no frames for rdf");
+        let parent = self.parent();
+        let (centers, targets) = select_pair(parent, options);
+        ensure!(!centers.is_empty() && !targets.is_empty(), "no atoms selected for rdf");
+
+        let numbers: Vec<_> = parent.numbers().collect();
+        let r = bin_centers(options);
+        let mut g = vec![0.0; r.len()];
+        let mut coordination_numbers = vec![0.0; r.len()];
+        for frame in self.iter() {
+            let lattice = frame.lattice().ok_or_else(|| format_err!("rdf requires periodic lattice"))?;
+            let particles = numbers.iter().copied().zip(frame.positions().iter().copied()).collect();
+            let (gi, cni) = compute_rdf(particles, lattice, &centers, &targets, options);
+            g.iter_mut().zip(gi).for_each(|(x, y)| *x += y);
+            coordination_numbers.iter_mut().zip(cni).for_each(|(x, y)| *x += y);
+        }
+        let n = self.nframes() as f64;
+        g.iter_mut().chain(coordination_numbers.iter_mut()).for_each(|x| *x /= n);
+        Ok(Rdf {
+            r,
+            g,
+            coordination_numbers,
+        })
+    }
+}
+// 2ba369a1 ends here
+
+// [[file:../gchemol-core.note::45990a88][45990a88]]
+#[test]
+fn test_rdf() {
+    use crate::Atom;
+    use std::convert::TryFrom;
+    use vecfx::approx::assert_relative_eq;
+
+    // rock salt as simple cubic lattice with alternating Na and Cl,
+    // spacing 2.0 in a periodic box of 4.0
+    let mut atoms = vec![];
+    for (i, j, k) in (0..2).flat_map(|i| (0..2).flat_map(move |j| (0..2).map(move |k| (i, j, k)))) {
+        let symbol = if (i + j + k) % 2 == 0 { "Na" } else { "Cl" };
+        atoms.push(Atom::new(symbol, [i as f64 * 2.0, j as f64 * 2.0, k as f64 * 2.0]));
+    }
+    let mut mol = Molecule::from_atoms(atoms);
+    let mut options = RdfOptions {
+        bin_width: 0.15,
+        cutoff: 3.6,
+        elements: None,
+    };
+    assert!(mol.rdf(&options).is_err());
+    mol.set_lattice(Lattice::from_params(4.0, 4.0, 4.0, 90.0, 90.0, 90.0));
+
+    // shells at 2.0 (6), 2.83 (12) and 3.46 (8)
+    let rdf = mol.rdf(&options).unwrap();
+    assert_eq!(rdf.r.len(), 24);
+    assert_relative_eq!(rdf.r[0], 0.075, epsilon = 1e-8);
+    assert_eq!(rdf.g[0], 0.0);
+    assert!(rdf.g[13] > 1.0);
+    let cn = &rdf.coordination_numbers;
+    assert_relative_eq!(cn[13], 6.0, epsilon = 1e-8);
+    assert_relative_eq!(cn[17], 6.0, epsilon = 1e-8);
+    assert_relative_eq!(cn[18], 18.0, epsilon = 1e-8);
+    assert_relative_eq!(cn[23], 26.0, epsilon = 1e-8);
+
+    // element pairs
+    options.elements = Some(("Na".into(), "Cl".into()));
+    let rdf = mol.rdf(&options).unwrap();
+    assert_relative_eq!(rdf.coordination_numbers[18], 6.0, epsilon = 1e-8);
+    assert_relative_eq!(rdf.coordination_numbers[23], 14.0, epsilon = 1e-8);
+    // g(r) = n_shell / (rho * V_shell), with rho = 4 / 64
+    let shell = 4.0 / 3.0 * std::f64::consts::PI * (2.1f64.powi(3) - 1.95f64.powi(3));
+    assert_relative_eq!(rdf.g[13], 6.0 / (4.0 / 64.0 * shell), epsilon = 1e-8);
+    options.elements = Some(("Na".into(), "Na".into()));
+    let rdf = mol.rdf(&options).unwrap();
+    assert_relative_eq!(rdf.coordination_numbers[13], 0.0, epsilon = 1e-8);
+    assert_relative_eq!(rdf.coordination_numbers[18], 12.0, epsilon = 1e-8);
+
+    // averaged over trajectory of identical frames
+    let traj = Trajectory::try_from(vec![mol.clone(), mol.clone()]).unwrap();
+    let rdf_traj = traj.rdf(&options).unwrap();
+    for (a, b) in rdf_traj.g.iter().zip(&rdf.g) {
+        assert_relative_eq!(a, b, epsilon = 1e-8);
+    }
+    options.elements = Some(("Na".into(), "K".into()));
+    assert!(traj.rdf(&options).is_err());
+}
+// 45990a88 ends here
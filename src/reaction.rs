@@ -0,0 +1,230 @@
+// [[file:../gchemol-core.note::60fb68d1][60fb68d1]]
+//! Detection of bond formation and breaking events along `Trajectory`
+// 60fb68d1 ends here
+
+// [[file:../gchemol-core.note::d4920bb7][d4920bb7]]
+use crate::common::*;
+use crate::connect::{guess_bonds, RebondOptions};
+use crate::formula::get_reduced_formula;
+use crate::trajectory::Trajectory;
+use crate::Molecule;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+// d4920bb7 ends here
+
+// [[file:../gchemol-core.note::be4e04c2][be4e04c2]]
+/// Options for detecting bond changes along trajectory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReactionOptions {
+    /// Options for creating bonds in each frame.
+    pub rebond: RebondOptions,
+
+    /// Looser options for keeping existing bonds, for hysteresis: a bond
+    /// formed in previous frame is broken only if it is not found using
+    /// these options. If not set, `rebond` is used for both.
+    pub breaking: Option<RebondOptions>,
+
+    /// The minimum number of consecutive frames a new bonding state must
+    /// persist to be accepted, for ignoring transient changes due to
+    /// vibrations.
+    pub min_lifetime: usize,
+}
+
+impl Default for ReactionOptions {
+    fn default() -> Self {
+        Self {
+            rebond: Molecule::rebond_options(),
+            breaking: None,
+            min_lifetime: 1,
+        }
+    }
+}
+
+/// The kind of bond change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum BondEventKind {
+    /// A new bond is formed.
+    Formed,
+    /// An existing bond is broken.
+    Broken,
+}
+
+/// A bond formation or breaking event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BondEvent {
+    /// The index of the frame where the change happens.
+    pub frame: usize,
+    /// Serial numbers of the two atoms, in ascending order.
+    pub atoms: (usize, usize),
+    /// Formed or broken.
+    pub kind: BondEventKind,
+}
+
+/// The result of bond change analysis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionAnalysis {
+    /// Bond events ordered by frame.
+    pub events: Vec<BondEvent>,
+
+    /// Sorted formulas of fragments in each frame.
+    pub fragments: Vec<Vec<String>>,
+}
+
+type BondSet = BTreeSet<(usize, usize)>;
+
+fn bond_set(mol: &Molecule, options: &RebondOptions) -> BondSet {
+    guess_bonds(mol, options).into_iter().map(|(i, j, _)| (i.min(j), i.max(j))).collect()
+}
+
+/// Bonds in each frame, with hysteresis applied.
+fn bonds_in_frames(traj: &Trajectory, options: &ReactionOptions) -> Vec<BondSet> {
+    let mut mol = traj.parent().clone();
+    let mut frames: Vec<BondSet> = vec![];
+    for conf in traj.frames.iter() {
+        conf.update_molecule(&mut mol);
+        let mut bonds = bond_set(&mol, &options.rebond);
+        if let (Some(breaking), Some(prev)) = (&options.breaking, frames.last()) {
+            let kept = bond_set(&mol, breaking);
+            bonds.extend(prev.intersection(&kept).copied());
+        }
+        frames.push(bonds);
+    }
+    frames
+}
+
+/// Filter bonding states of a pair over frames, accepting changes
+/// persisting at least `min_lifetime` frames.
+fn filter_states(states: &[bool], min_lifetime: usize) -> Vec<bool> {
+    let mut filtered: Vec<bool> = Vec::with_capacity(states.len());
+    for (t, &s) in states.iter().enumerate() {
+        let state = match filtered.last() {
+            Some(&prev) if prev != s => {
+                let persist = states.get(t..t + min_lifetime).is_some_and(|w| w.iter().all(|&x| x == s));
+                if persist {
+                    s
+                } else {
+                    prev
+                }
+            }
+            _ => s,
+        };
+        filtered.push(state);
+    }
+    filtered
+}
+
+/// Sorted formulas of fragments connected by `bonds`.
+fn fragment_formulas(mol: &Molecule, bonds: &BondSet) -> Vec<String> {
+    let index: HashMap<_, _> = mol.numbers().enumerate().map(|(k, i)| (i, k)).collect();
+    let mut root: Vec<usize> = (0..index.len()).collect();
+    fn find(root: &mut [usize], mut k: usize) -> usize {
+        while root[k] != k {
+            root[k] = root[root[k]];
+            k = root[k];
+        }
+        k
+    }
+    for (i, j) in bonds {
+        let (a, b) = (find(&mut root, index[i]), find(&mut root, index[j]));
+        root[a] = b;
+    }
+    let mut fragments: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+    for (sn, atom) in mol.atoms() {
+        fragments.entry(find(&mut root, index[&sn])).or_default().push(atom.symbol());
+    }
+    fragments.into_values().map(get_reduced_formula).sorted().collect()
+}
+
+impl Trajectory {
+    /// Detect bond formation and breaking events by rebonding each
+    /// frame, and track formulas of fragments over frames.
+    ///
+    /// # NOTE
+    /// * Bonds in the first frame are taken as the initial state; no
+    ///   events are reported for them.
+    /// * With `min_lifetime` larger than 1, changes in the last frames
+    ///   which do not last long enough are ignored.
+    pub fn detect_bond_events(&self, options: &ReactionOptions) -> ReactionAnalysis {
+        let raw = bonds_in_frames(self, options);
+        let pairs: BondSet = raw.iter().flatten().copied().collect();
+        let mut filtered = vec![BondSet::new(); raw.len()];
+        let mut events = vec![];
+        for pair in pairs {
+            let states: Vec<_> = raw.iter().map(|bonds| bonds.contains(&pair)).collect();
+            let states = filter_states(&states, options.min_lifetime.max(1));
+            for (t, &s) in states.iter().enumerate() {
+                if s {
+                    filtered[t].insert(pair);
+                }
+                if t > 0 && s != states[t - 1] {
+                    let kind = if s { BondEventKind::Formed } else { BondEventKind::Broken };
+                    events.push(BondEvent { frame: t, atoms: pair, kind });
+                }
+            }
+        }
+        events.sort_by_key(|e| (e.frame, e.atoms, e.kind));
+
+        let fragments = filtered.iter().map(|bonds| fragment_formulas(self.parent(), bonds)).collect();
+        ReactionAnalysis { events, fragments }
+    }
+}
+// be4e04c2 ends here
+
+// [[file:../gchemol-core.note::4ea742df][4ea742df]]
+#[test]
+fn test_bond_events() {
+    use crate::Atom;
+    use std::convert::TryFrom;
+
+    // H + H2 -> H2 + H exchange along x, with H2-H3 stretched
+    // transiently in frame 5
+    let xs = [
+        [0.0, 0.74, 4.0],
+        [0.0, 0.74, 3.0],
+        [0.0, 0.74, 2.0],
+        [-2.0, 0.74, 1.48],
+        [-3.0, 0.74, 1.48],
+        [-4.0, 0.74, 1.74],
+        [-5.0, 0.74, 1.48],
+        [-6.0, 0.74, 1.48],
+    ];
+    let mols: Vec<_> = xs
+        .iter()
+        .map(|x| Molecule::from_atoms(x.iter().map(|&xi| Atom::new("H", [xi, 0.0, 0.0]))))
+        .collect();
+    let traj = Trajectory::try_from(mols).unwrap();
+
+    let mut options = ReactionOptions {
+        rebond: RebondOptions::default(),
+        ..Default::default()
+    };
+    let analysis = traj.detect_bond_events(&options);
+    let events: Vec<_> = analysis.events.iter().map(|e| (e.frame, e.atoms, e.kind)).collect();
+    assert_eq!(
+        events,
+        vec![
+            (3, (1, 2), BondEventKind::Broken),
+            (3, (2, 3), BondEventKind::Formed),
+            (5, (2, 3), BondEventKind::Broken),
+            (6, (2, 3), BondEventKind::Formed),
+        ]
+    );
+    assert_eq!(analysis.fragments[0], vec!["H", "H2"]);
+    assert_eq!(analysis.fragments[5], vec!["H", "H", "H"]);
+
+    // the transient breaking is ignored with minimum lifetime
+    options.min_lifetime = 2;
+    let analysis = traj.detect_bond_events(&options);
+    assert_eq!(analysis.events.len(), 2);
+    assert!(analysis.fragments.iter().all(|f| f == &vec!["H", "H2"]));
+
+    // or with hysteresis
+    options.min_lifetime = 1;
+    let mut breaking = RebondOptions::default();
+    breaking.bond_tolerance = Some(0.8);
+    options.breaking = Some(breaking);
+    let analysis = traj.detect_bond_events(&options);
+    assert_eq!(analysis.events.len(), 2);
+}
+// 4ea742df ends here
@@ -0,0 +1,194 @@
+// [[file:../gchemol-core.note::0c1d15c2][0c1d15c2]]
+//! Residue/chain hierarchy of biomolecular structures on top of atom groups
+// 0c1d15c2 ends here
+
+// [[file:../gchemol-core.note::8964ab97][8964ab97]]
+use crate::common::*;
+use crate::{AtomGroup, Molecule};
+// 8964ab97 ends here
+
+// [[file:../gchemol-core.note::eb602d26][eb602d26]]
+/// The property key for the alternate location indicator of atoms.
+pub const ALTLOC_KEY: &str = "altloc";
+
+/// The group property key for residue information.
+const RESIDUE_KEY: &str = "residue";
+
+/// The prefix of group names for residues.
+const RESIDUE_GROUP_PREFIX: &str = "residue:";
+
+/// A residue in biomolecular structure, identified by chain ID, residue
+/// number and insertion code. Residues are ordered by chain, then by
+/// residue number and insertion code.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Residue {
+    /// Chain identifier, such as "A".
+    pub chain: String,
+    /// Residue sequence number.
+    pub number: isize,
+    /// Code for inserted residues, such as 'A' in "52A".
+    pub insertion_code: Option<char>,
+    /// Residue name, such as "ALA".
+    pub name: String,
+}
+
+impl Residue {
+    /// Create residue `name` numbered `number` in `chain`.
+    pub fn new(chain: &str, name: &str, number: isize) -> Self {
+        Self {
+            chain: chain.into(),
+            number,
+            insertion_code: None,
+            name: name.into(),
+        }
+    }
+
+    /// Set insertion code of residue.
+    pub fn with_insertion_code(mut self, code: char) -> Self {
+        self.insertion_code = Some(code);
+        self
+    }
+
+    /// The name of atom group holding atoms of this residue.
+    fn group_name(&self) -> String {
+        let code = self.insertion_code.map(String::from).unwrap_or_default();
+        format!("{RESIDUE_GROUP_PREFIX}{}:{}{code}", self.chain, self.number)
+    }
+}
+// eb602d26 ends here
+
+// [[file:../gchemol-core.note::f80eccf7][f80eccf7]]
+/// Residue and chain hierarchy
+impl Molecule {
+    /// Iterate over residues with their atom groups in arbitrary order.
+    fn residue_groups(&self) -> impl Iterator<Item = (Residue, &AtomGroup)> {
+        self.groups
+            .iter()
+            .filter(|(name, _)| name.starts_with(RESIDUE_GROUP_PREFIX))
+            .filter_map(|(_, g)| Some((g.properties.load(RESIDUE_KEY).ok()?, g)))
+    }
+
+    /// Assign atoms `atoms` to `residue`. Atoms are removed from the
+    /// residues they belonged to before. Return None if any serial
+    /// number is invalid.
+    ///
+    /// # NOTE
+    /// * Residues are stored as atom groups, so that they follow atom
+    ///   renumbering and removal, and are kept in sub molecules.
+    pub fn set_residue(&mut self, atoms: &[usize], residue: &Residue) -> Option<()> {
+        let nodes: Vec<_> = atoms.iter().map(|&i| self.get_node_index(i).copied()).collect::<Option<_>>()?;
+        for group in self.groups.iter_mut().filter_map(|(name, g)| name.starts_with(RESIDUE_GROUP_PREFIX).then_some(g)) {
+            nodes.iter().for_each(|&n| group.discard(n));
+        }
+        self.groups.retain(|name, g| !(name.starts_with(RESIDUE_GROUP_PREFIX) && g.is_empty()));
+
+        let name = residue.group_name();
+        let mut group = self.groups.remove(&name).unwrap_or_default();
+        for &i in atoms {
+            group.insert(self, i);
+        }
+        group.properties.store(RESIDUE_KEY, residue).ok()?;
+        self.groups.insert(name, group);
+        Some(())
+    }
+
+    /// Return the residue atom `sn` belongs to.
+    pub fn get_residue(&self, sn: usize) -> Option<Residue> {
+        self.residue_groups().find(|(_, g)| g.contains(self, sn)).map(|(r, _)| r)
+    }
+
+    /// Iterate over residues and serial numbers of their atoms, ordered
+    /// by chain, residue number and insertion code.
+    pub fn residues(&self) -> impl Iterator<Item = (Residue, Vec<usize>)> + '_ {
+        self.residue_groups()
+            .filter(|(_, g)| !g.is_empty())
+            .map(|(r, g)| (r, g.serial_numbers(self).collect()))
+            .sorted_by(|a, b| a.0.cmp(&b.0))
+    }
+
+    /// Iterate over residues in `chain` and serial numbers of their atoms.
+    pub fn residues_in_chain<'a>(&'a self, chain: &'a str) -> impl Iterator<Item = (Residue, Vec<usize>)> + 'a {
+        self.residues().filter(move |(r, _)| r.chain == chain)
+    }
+
+    /// Return the identifiers of all chains in sorted order.
+    pub fn chains(&self) -> Vec<String> {
+        self.residues().map(|(r, _)| r.chain).dedup().collect()
+    }
+
+    /// Set the alternate location indicator of atom `sn`. Remove it if
+    /// `altloc` is None.
+    ///
+    /// # Panics
+    ///
+    /// * panics if there is no atom associated with `sn`.
+    pub fn set_altloc(&mut self, sn: usize, altloc: Option<char>) {
+        let atom = self.get_atom_mut(sn).expect("invalid atom sn");
+        match altloc {
+            Some(c) => atom.properties.store(ALTLOC_KEY, c).unwrap(),
+            None => atom.properties.discard(ALTLOC_KEY),
+        }
+    }
+
+    /// Return the alternate location indicator of atom `sn`.
+    pub fn get_altloc(&self, sn: usize) -> Option<char> {
+        self.get_atom(sn)?.properties.load(ALTLOC_KEY).ok()
+    }
+
+    /// Keep only alternate location `altloc` by removing atoms in other
+    /// alternate locations. Atoms without alternate location are kept.
+    pub fn select_altloc(&mut self, altloc: char) {
+        let removed: Vec<_> = self.numbers().filter(|&i| self.get_altloc(i).is_some_and(|c| c != altloc)).collect();
+        for i in removed {
+            self.remove_atom(i);
+        }
+    }
+}
+// f80eccf7 ends here
+
+// [[file:../gchemol-core.note::f5b7ba65][f5b7ba65]]
+#[test]
+fn test_residue_hierarchy() {
+    use crate::Atom;
+
+    // two chains: A with GLY 1 and SER 2/2A, B with HOH 101
+    let mut mol = Molecule::from_atoms((0..8).map(|i| Atom::new("C", [i as f64, 0.0, 0.0])));
+    mol.set_residue(&[1, 2], &Residue::new("A", "GLY", 1)).unwrap();
+    mol.set_residue(&[3, 4, 5], &Residue::new("A", "SER", 2)).unwrap();
+    mol.set_residue(&[6], &Residue::new("A", "SER", 2).with_insertion_code('A')).unwrap();
+    mol.set_residue(&[7, 8], &Residue::new("B", "HOH", 101)).unwrap();
+    assert!(mol.set_residue(&[9], &Residue::new("B", "HOH", 102)).is_none());
+
+    assert_eq!(mol.chains(), vec!["A", "B"]);
+    let residues = mol.residues().collect_vec();
+    assert_eq!(residues.len(), 4);
+    assert_eq!(residues[0].0.name, "GLY");
+    assert_eq!(residues[2].0.insertion_code, Some('A'));
+    assert_eq!(residues[2].1, vec![6]);
+    assert_eq!(mol.residues_in_chain("B").map(|(r, _)| r.number).collect_vec(), vec![101]);
+    assert_eq!(mol.get_residue(4).unwrap().name, "SER");
+
+    // reassigning atoms moves them out of their old residues
+    mol.set_residue(&[1, 2], &Residue::new("B", "HOH", 102)).unwrap();
+    assert_eq!(mol.residues_in_chain("A").count(), 2);
+    assert_eq!(mol.get_residue(1).unwrap().number, 102);
+
+    // alternate locations
+    mol.set_altloc(4, Some('A'));
+    mol.set_altloc(5, Some('B'));
+    assert_eq!(mol.get_altloc(4), Some('A'));
+    assert_eq!(mol.get_altloc(3), None);
+    mol.select_altloc('A');
+    assert_eq!(mol.natoms(), 7);
+    assert_eq!(mol.get_residue(3).unwrap().name, "SER");
+    assert_eq!(mol.residues_in_chain("A").next().unwrap().1, vec![3, 4]);
+
+    // sub molecule keeps the hierarchy
+    let sub = mol.get_sub_molecule(&[3, 4, 7]).unwrap();
+    assert_eq!(sub.chains(), vec!["A", "B"]);
+    assert_eq!(sub.residues().map(|(r, a)| (r.name, a)).collect_vec(), vec![("SER".into(), vec![3, 4]), ("HOH".into(), vec![7])]);
+    assert_eq!(sub.get_altloc(4), Some('A'));
+    let frags = mol.fragmented().collect_vec();
+    assert!(frags.iter().all(|m| m.residues().count() == 1));
+}
+// f5b7ba65 ends here
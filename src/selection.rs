@@ -1,6 +1,6 @@
 // [[file:../gchemol-core.note::4c6fdd8b][4c6fdd8b]]
 use crate::common::*;
-use crate::{Atom, Molecule};
+use crate::{Atom, Lattice, Molecule};
 
 use std::collections::HashSet;
 
@@ -30,9 +30,16 @@ fn select_atoms_by_expanding_bond_(mol: &Molecule, m: usize, n: usize) -> HashSe
 /// N.B. The neighbor node index is defined using atom serial number
 fn create_neighborhood_probe(mol: &Molecule) -> Neighborhood {
     let particles: Vec<_> = mol.atoms().map(|(i, a)| (i, a.position())).collect();
+    neighborhood_probe_from(particles, mol.lattice.as_ref())
+}
+
+/// Return a `Neighborhood` struct for probing nearest neighbors in
+/// `particles` as pairs of node index and position, with optional
+/// periodic `lattice`.
+pub(crate) fn neighborhood_probe_from(particles: Vec<(usize, [f64; 3])>, lattice: Option<&Lattice>) -> Neighborhood {
     let mut nh = Neighborhood::new();
     nh.update(particles);
-    if let Some(lat) = mol.lattice {
+    if let Some(lat) = lattice {
         nh.set_lattice(lat.matrix().into());
     }
 
@@ -84,3 +91,481 @@ impl Molecule {
     }
 }
 // 90d8094c ends here
+
+// [[file:../gchemol-core.note::6725bde5][6725bde5]]
+use std::collections::BTreeSet;
+
+/// Comparison operators in coordinate clauses.
+#[derive(Debug, Clone, Copy)]
+enum Compare {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Compare {
+    fn test(&self, a: f64, b: f64) -> bool {
+        match self {
+            Compare::Lt => a < b,
+            Compare::Le => a <= b,
+            Compare::Gt => a > b,
+            Compare::Ge => a >= b,
+            Compare::Eq => a == b,
+            Compare::Ne => a != b,
+        }
+    }
+}
+
+/// Primitive clauses selecting atoms by their own attributes.
+#[derive(Debug, Clone)]
+enum Clause {
+    All,
+    None,
+    Element(Vec<String>),
+    Label(Vec<String>),
+    Serial(Vec<(usize, usize)>),
+    Group(Vec<String>),
+    Frozen,
+    Ring(Option<usize>),
+    Fragment(Vec<(usize, usize)>),
+    Chain(Vec<String>),
+    ResName(Vec<String>),
+    ResId(Vec<(isize, isize)>),
+    Coord {
+        axis: usize,
+        fractional: bool,
+        cmp: Compare,
+        value: f64,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Clause(Clause),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Within(f64, Box<Expr>),
+    SameFragment(Box<Expr>),
+}
+// 6725bde5 ends here
+
+// [[file:../gchemol-core.note::3dcdc583][3dcdc583]]
+const RESERVED: &[&str] = &["and", "or", "not", "(", ")"];
+
+/// Split selection expression into words, parentheses and comparison
+/// operators.
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() || "()<>=!".contains(c) {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            match c {
+                '(' | ')' => tokens.push(c.to_string()),
+                '<' | '>' | '=' | '!' => {
+                    let mut op = c.to_string();
+                    if chars.next_if_eq(&'=').is_some() {
+                        op.push('=');
+                    }
+                    tokens.push(op);
+                }
+                _ => {}
+            }
+        } else {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+/// Parse a range like "3", "3-5" or "-2--1".
+fn parse_range<T: FromStr + Copy>(s: &str) -> Option<(T, T)> {
+    match s.get(1..).and_then(|t| t.find('-')) {
+        Some(i) => Some((s[..i + 1].parse().ok()?, s[i + 2..].parse().ok()?)),
+        None => {
+            let x = s.parse().ok()?;
+            Some((x, x))
+        }
+    }
+}
+
+/// Recursive descent parser with precedence: `or` < `and` < `not`,
+/// `within` and `same fragment as`.
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|s| s.as_str())
+    }
+
+    fn next(&mut self) -> Result<&str> {
+        let token = self.tokens.get(self.pos).ok_or_else(|| format_err!("unexpected end of selection"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, token: &str) -> Result<()> {
+        let found = self.next()?;
+        ensure!(found == token, "expected {token:?}, found {found:?}");
+        Ok(())
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T> {
+        let token = self.next()?;
+        token.parse().map_err(|_| format_err!("invalid number: {token:?}"))
+    }
+
+    /// Parse one or more words until a reserved token.
+    fn words(&mut self) -> Result<Vec<String>> {
+        let mut words = vec![];
+        while let Some(w) = self.peek().filter(|w| !RESERVED.contains(w)) {
+            words.push(w.to_owned());
+            self.pos += 1;
+        }
+        ensure!(!words.is_empty(), "missing values near token {}", self.pos);
+        Ok(words)
+    }
+
+    fn ranges<T: FromStr + Copy>(&mut self) -> Result<Vec<(T, T)>> {
+        self.words()?
+            .iter()
+            .map(|w| parse_range(w).ok_or_else(|| format_err!("invalid range: {w:?}")))
+            .collect()
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some("or") {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut left = self.parse_unary()?;
+        while self.peek() == Some("and") {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.parse_unary()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some("not") => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some("within") => {
+                self.pos += 1;
+                let r: f64 = self.number()?;
+                ensure!(r.is_sign_positive(), "invalid cutoff distance {r:?}");
+                self.expect("of")?;
+                Ok(Expr::Within(r, Box::new(self.parse_unary()?)))
+            }
+            Some("same") => {
+                self.pos += 1;
+                self.expect("fragment")?;
+                self.expect("as")?;
+                Ok(Expr::SameFragment(Box::new(self.parse_unary()?)))
+            }
+            Some("(") => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            _ => Ok(Expr::Clause(self.parse_clause()?)),
+        }
+    }
+
+    fn parse_clause(&mut self) -> Result<Clause> {
+        let keyword = self.next()?.to_owned();
+        let clause = match keyword.as_str() {
+            "all" => Clause::All,
+            "none" => Clause::None,
+            "element" => Clause::Element(self.words()?),
+            "label" => Clause::Label(self.words()?),
+            "serial" => Clause::Serial(self.ranges()?),
+            "group" => Clause::Group(self.words()?),
+            "frozen" => Clause::Frozen,
+            "ring" => {
+                let size = self.peek().and_then(|w| w.parse().ok());
+                if size.is_some() {
+                    self.pos += 1;
+                }
+                Clause::Ring(size)
+            }
+            "fragment" => Clause::Fragment(self.ranges()?),
+            "chain" => Clause::Chain(self.words()?),
+            "resname" => Clause::ResName(self.words()?),
+            "resid" => Clause::ResId(self.ranges()?),
+            "x" | "y" | "z" | "fx" | "fy" | "fz" => {
+                let fractional = keyword.starts_with('f');
+                let axis = match keyword.chars().last() {
+                    Some('x') => 0,
+                    Some('y') => 1,
+                    _ => 2,
+                };
+                let cmp = match self.next()? {
+                    "<" => Compare::Lt,
+                    "<=" => Compare::Le,
+                    ">" => Compare::Gt,
+                    ">=" => Compare::Ge,
+                    "=" | "==" => Compare::Eq,
+                    "!=" => Compare::Ne,
+                    op => bail!("invalid comparison operator: {op:?}"),
+                };
+                let value = self.number()?;
+                Clause::Coord { axis, fractional, cmp, value }
+            }
+            _ => bail!("unknown selection keyword: {keyword:?}"),
+        };
+        Ok(clause)
+    }
+}
+// 3dcdc583 ends here
+
+// [[file:../gchemol-core.note::98c1e300][98c1e300]]
+/// Connected fragments ordered by their first atom.
+fn fragments(mol: &Molecule) -> Vec<Vec<usize>> {
+    mol.graph()
+        .connected_components_node_indices()
+        .map(|nodes| nodes.iter().map(|&n| mol.atom_sn(n)).sorted().collect_vec())
+        .sorted()
+        .collect()
+}
+
+fn in_ranges<T: PartialOrd>(ranges: &[(T, T)], x: T) -> bool {
+    ranges.iter().any(|(a, b)| a <= &x && &x <= b)
+}
+
+impl Clause {
+    fn eval(&self, mol: &Molecule) -> Result<BTreeSet<usize>> {
+        let filter = |f: &dyn Fn(usize, &Atom) -> bool| mol.atoms().filter(|&(i, a)| f(i, a)).map(|(i, _)| i).collect();
+        let selected = match self {
+            Clause::All => mol.numbers().collect(),
+            Clause::None => BTreeSet::new(),
+            Clause::Element(symbols) => filter(&|_, a| symbols.iter().any(|s| s == a.symbol())),
+            Clause::Label(labels) => filter(&|_, a| a.get_label().is_some_and(|l| labels.iter().any(|s| s == l))),
+            Clause::Serial(ranges) => filter(&|i, _| in_ranges(ranges, i)),
+            Clause::Frozen => filter(&|_, a| a.is_fixed()),
+            Clause::Group(names) => {
+                let mut selected = BTreeSet::new();
+                for name in names {
+                    let group = mol.get_group(name).ok_or_else(|| format_err!("no such group: {name:?}"))?;
+                    selected.extend(group.serial_numbers(mol));
+                }
+                selected
+            }
+            Clause::Ring(None) => {
+                let (graph, numbers) = mol.compact_graph();
+                let ring = crate::substructure::find_ring_bonds(&graph);
+                graph
+                    .raw_edges()
+                    .iter()
+                    .zip(ring)
+                    .filter(|(_, r)| *r)
+                    .flat_map(|(e, _)| [numbers[e.source().index()], numbers[e.target().index()]])
+                    .collect()
+            }
+            Clause::Ring(Some(n)) => mol.find_rings(*n).into_iter().filter(|r| r.len() == *n).flatten().collect(),
+            Clause::Fragment(ranges) => fragments(mol)
+                .into_iter()
+                .enumerate()
+                .filter(|(k, _)| in_ranges(ranges, k + 1))
+                .flat_map(|(_, f)| f)
+                .collect(),
+            Clause::Chain(chains) => residue_atoms(mol, |r| chains.contains(&r.chain)),
+            Clause::ResName(names) => residue_atoms(mol, |r| names.contains(&r.name)),
+            Clause::ResId(ranges) => residue_atoms(mol, |r| in_ranges(ranges, r.number)),
+            Clause::Coord {
+                axis,
+                fractional,
+                cmp,
+                value,
+            } => {
+                let lat = match (fractional, mol.lattice) {
+                    (false, _) => None,
+                    (true, Some(lat)) => Some(lat),
+                    (true, None) => bail!("fractional coordinates require lattice"),
+                };
+                filter(&|_, a| {
+                    let p = match lat {
+                        Some(lat) => lat.to_frac(a.position()).into(),
+                        None => a.position(),
+                    };
+                    cmp.test(p[*axis], *value)
+                })
+            }
+        };
+        Ok(selected)
+    }
+}
+
+fn residue_atoms(mol: &Molecule, f: impl Fn(&crate::Residue) -> bool) -> BTreeSet<usize> {
+    mol.residues().filter(|(r, _)| f(r)).flat_map(|(_, atoms)| atoms).collect()
+}
+
+impl Expr {
+    fn eval(&self, mol: &Molecule) -> Result<BTreeSet<usize>> {
+        let selected = match self {
+            Expr::Clause(c) => c.eval(mol)?,
+            Expr::Not(e) => {
+                let excluded = e.eval(mol)?;
+                mol.numbers().filter(|i| !excluded.contains(i)).collect()
+            }
+            Expr::And(a, b) => &a.eval(mol)? & &b.eval(mol)?,
+            Expr::Or(a, b) => &a.eval(mol)? | &b.eval(mol)?,
+            Expr::Within(r, e) => {
+                let mut selected = e.eval(mol)?;
+                let nh = create_neighborhood_probe(mol);
+                let found: Vec<_> = selected.iter().flat_map(|&i| nh.neighbors(i, *r).map(|n| n.node)).collect();
+                selected.extend(found);
+                selected
+            }
+            Expr::SameFragment(e) => {
+                let selected = e.eval(mol)?;
+                fragments(mol)
+                    .into_iter()
+                    .filter(|f| f.iter().any(|i| selected.contains(i)))
+                    .flatten()
+                    .collect()
+            }
+        };
+        Ok(selected)
+    }
+}
+// 98c1e300 ends here
+
+// [[file:../gchemol-core.note::1cb2a9c4][1cb2a9c4]]
+/// A parsed atom selection expression in a small language similar to
+/// that of VMD or PyMOL, such as "element O and within 3.0 of group ads"
+/// or "not frozen and z > 10".
+///
+/// # Syntax
+///
+/// Clauses can be combined with `and`, `or`, `not` and parentheses:
+///
+/// * `all`, `none`
+/// * `element O N`: atoms of any listed element
+/// * `label O1 H2`: atoms with any listed label
+/// * `serial 1 3-5`: atoms by serial numbers or inclusive ranges
+/// * `group ads`: atoms in named atom groups
+/// * `frozen`: atoms with all coordinates frozen
+/// * `ring`: atoms in any ring; `ring 6`: atoms in six-membered rings
+/// * `fragment 1 2-3`: atoms in connected fragments, counted from 1 in
+///   the order of their first atom
+/// * `chain A`, `resname ALA GLY`, `resid 1-10`: atoms by residue
+/// * `x > 10`, `fz <= 0.5`: Cartesian (x, y, z) or fractional (fx, fy,
+///   fz) coordinates compared using `<`, `<=`, `>`, `>=`, `==`, `!=`
+/// * `within 3.0 of <sel>`: atoms within a distance of any selected
+///   atom, including periodic images for periodic structure
+/// * `same fragment as <sel>`: whole fragments of selected atoms
+///
+/// `not`, `within` and `same fragment as` apply to the clause following
+/// them only; use parentheses for compound selection.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    expr: Expr,
+}
+
+impl FromStr for Selection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser { tokens: tokenize(s), pos: 0 };
+        let expr = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            bail!("unexpected token in selection: {token:?}");
+        }
+        Ok(Self { expr })
+    }
+}
+
+impl Selection {
+    /// Evaluate selection in `mol`. Return serial numbers of selected
+    /// atoms in ascending order.
+    pub fn evaluate(&self, mol: &Molecule) -> Result<Vec<usize>> {
+        Ok(self.expr.eval(mol)?.into_iter().collect())
+    }
+}
+
+impl Molecule {
+    /// Return serial numbers of atoms selected by expression `selection`,
+    /// in ascending order. See [Selection](struct.Selection.html) for
+    /// syntax.
+    pub fn select(&self, selection: &str) -> Result<Vec<usize>> {
+        selection.parse::<Selection>()?.evaluate(self)
+    }
+}
+// 1cb2a9c4 ends here
+
+// [[file:../gchemol-core.note::5c6a83e5][5c6a83e5]]
+#[test]
+fn test_selection_language() {
+    use crate::{Bond, Lattice, Residue};
+
+    // a six-membered carbon ring, a water molecule across the periodic
+    // boundary in z, and two frozen Pt atoms
+    let mut mol = Molecule::from_atoms((1..=6).map(|i| Atom::new("C", [i as f64, 1.0, 5.0])));
+    for i in 1..=6 {
+        mol.add_bond(i, i % 6 + 1, Bond::single());
+    }
+    mol.add_atom(7, Atom::new("O", [5.0, 5.0, 9.5]));
+    mol.add_atom(8, Atom::new("H", [5.0, 5.9, 9.5]));
+    mol.add_atom(9, Atom::new("H", [5.0, 5.0, 0.3]));
+    mol.add_bond(7, 8, Bond::single());
+    mol.add_bond(7, 9, Bond::single());
+    for (i, x) in [(10, 9.5), (11, 0.5)] {
+        let mut pt = Atom::new("Pt", [x, 9.5, 5.0]);
+        pt.set_freezing([true; 3]);
+        mol.add_atom(i, pt);
+    }
+    mol.define_group("ads", &[7, 8, 9]);
+    mol.set_residue(&[7, 8, 9], &Residue::new("W", "HOH", 1));
+
+    assert_eq!(mol.select("element O").unwrap(), vec![7]);
+    assert_eq!(mol.select("element H and within 1.0 of element O").unwrap(), vec![8]);
+    assert_eq!(mol.select("not frozen and z > 9").unwrap(), vec![7, 8]);
+    assert_eq!(mol.select("ring").unwrap(), vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!(mol.select("ring 6 and serial 2-3").unwrap(), vec![2, 3]);
+    assert!(mol.select("ring 5").unwrap().is_empty());
+    assert_eq!(mol.select("serial 1-3 10").unwrap(), vec![1, 2, 3, 10]);
+    assert_eq!(mol.select("same fragment as serial 8").unwrap(), vec![7, 8, 9]);
+    assert_eq!(mol.select("fragment 1").unwrap(), vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!(mol.select("group ads and not element H").unwrap(), vec![7]);
+    assert_eq!(mol.select("resname HOH and not (element O or serial 9)").unwrap(), vec![8]);
+    mol.get_atom_mut(2).unwrap().set_label("C2");
+    assert_eq!(mol.select("label C2 O1").unwrap(), vec![2]);
+    assert_eq!(mol.select("within 1.1 of serial 10").unwrap(), vec![10]);
+
+    // errors
+    assert!(mol.select("element").is_err());
+    assert!(mol.select("group foo").is_err());
+    assert!(mol.select("x >> 1").is_err());
+    assert!(mol.select("(element O").is_err());
+    assert!(mol.select("element O extra )").is_err());
+    assert!(mol.select("fz > 0.5").is_err());
+
+    // periodic images
+    mol.set_lattice(Lattice::from_params(10.0, 10.0, 10.0, 90.0, 90.0, 90.0));
+    assert_eq!(mol.select("element H and within 1.0 of element O").unwrap(), vec![8, 9]);
+    assert_eq!(mol.select("within 1.1 of serial 10").unwrap(), vec![10, 11]);
+    assert_eq!(mol.select("(element Pt or element O) and fz > 0.9").unwrap(), vec![7]);
+}
+// 5c6a83e5 ends here
@@ -0,0 +1,236 @@
+// [[file:../gchemol-core.note::2d04fb16][2d04fb16]]
+//! Expand the asymmetric unit of a crystal with space group operations.
+// 2d04fb16 ends here
+
+// [[file:../gchemol-core.note::b6e26942][b6e26942]]
+use crate::common::*;
+use crate::lattice::Lattice;
+use crate::{Atom, Molecule, Vector3f};
+
+use vecfx::Matrix3f;
+// b6e26942 ends here
+
+// [[file:../gchemol-core.note::158244c3][158244c3]]
+/// The property key for the site multiplicity of atoms generated by
+/// [expand_by_symmetry](struct.Molecule.html#method.expand_by_symmetry).
+pub const SITE_MULTIPLICITY_KEY: &str = "site_multiplicity";
+
+/// The property key for the serial number of the parent atom in the
+/// asymmetric unit.
+pub const ASYMMETRIC_SITE_KEY: &str = "asymmetric_site";
+
+/// A crystallographic symmetry operation acting on fractional
+/// coordinates: x' = R x + t
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct SymmetryOperation {
+    /// The rotational part R in row major
+    pub rotation: [[f64; 3]; 3],
+    /// The translational part t in fractional coordinates
+    pub translation: [f64; 3],
+}
+
+impl Default for SymmetryOperation {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl SymmetryOperation {
+    /// Construct from rotational part `rotation` and translational part
+    /// `translation`.
+    pub fn new(rotation: [[f64; 3]; 3], translation: [f64; 3]) -> Self {
+        Self { rotation, translation }
+    }
+
+    /// The identity operation.
+    pub fn identity() -> Self {
+        Self::new([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], [0.0; 3])
+    }
+
+    /// Apply the operation to fractional coordinates `frac`.
+    pub fn apply<P: Into<Vector3f>>(&self, frac: P) -> Vector3f {
+        let r = Matrix3f::from_row_slice(&self.rotation.concat());
+        let t: Vector3f = self.translation.into();
+        r * frac.into() + t
+    }
+}
+
+fn parse_number(s: &str) -> Result<f64> {
+    if let Some((a, b)) = s.split_once('/') {
+        let a: f64 = a.parse()?;
+        let b: f64 = b.parse()?;
+        ensure!(b != 0.0, "invalid fraction: {s:?}");
+        Ok(a / b)
+    } else {
+        Ok(s.parse()?)
+    }
+}
+
+/// Parse one component of the symmetry operation in CIF style, such
+/// as "-x+1/2", or "x-y".
+fn parse_component(s: &str) -> Result<([f64; 3], f64)> {
+    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+    ensure!(!s.is_empty(), "empty symmetry operation component");
+
+    // split into signed terms: -x+1/2 ==> -x, +1/2
+    let mut terms = vec![];
+    let mut term = String::new();
+    for c in s.chars() {
+        if (c == '+' || c == '-') && !term.is_empty() {
+            terms.push(std::mem::take(&mut term));
+        }
+        term.push(c);
+    }
+    terms.push(term);
+
+    let mut rot = [0.0; 3];
+    let mut trans = 0.0;
+    for term in terms {
+        let (sign, body) = match term.strip_prefix('-') {
+            Some(body) => (-1.0, body),
+            None => (1.0, term.trim_start_matches('+')),
+        };
+        ensure!(!body.is_empty(), "invalid symmetry operation component: {s:?}");
+        let axis = match body.chars().last() {
+            Some('x') => Some(0),
+            Some('y') => Some(1),
+            Some('z') => Some(2),
+            _ => None,
+        };
+        if let Some(i) = axis {
+            // coefficient before the variable, e.g. 2x or 1/2*x
+            let coef = body[..body.len() - 1].trim_end_matches('*');
+            let coef = if coef.is_empty() { 1.0 } else { parse_number(coef)? };
+            rot[i] += sign * coef;
+        } else {
+            trans += sign * parse_number(body)?;
+        }
+    }
+
+    Ok((rot, trans))
+}
+
+impl FromStr for SymmetryOperation {
+    type Err = Error;
+
+    /// Parse symmetry operation in CIF style, such as "-x+1/2, y, -z".
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().trim_matches(|c| c == '\'' || c == '"');
+        let parts: Vec<_> = s.split(',').collect();
+        ensure!(parts.len() == 3, "invalid symmetry operation: {s:?}");
+
+        let mut op = Self::new([[0.0; 3]; 3], [0.0; 3]);
+        for (i, part) in parts.into_iter().enumerate() {
+            let (rot, trans) = parse_component(part).with_context(|| format!("invalid symmetry operation: {s:?}"))?;
+            op.rotation[i] = rot;
+            op.translation[i] = trans;
+        }
+
+        Ok(op)
+    }
+}
+// 158244c3 ends here
+
+// [[file:../gchemol-core.note::962dc654][962dc654]]
+/// Test if site `p` overlaps with any site in `sites` under the
+/// minimum image convention. All positions are in Cartesian coordinates.
+fn overlapping(lat: &Lattice, sites: &[Vector3f], p: Vector3f, symprec: f64) -> bool {
+    sites.iter().any(|&q| lat.distance(p, q) < symprec)
+}
+
+impl Molecule {
+    /// Generate the full unit cell by applying symmetry operations `ops`
+    /// to atoms in the asymmetric unit. Return None if `Molecule` is not
+    /// periodic, `ops` is empty, or `symprec` is not positive.
+    ///
+    /// # Parameters
+    /// * ops: space group operations on fractional coordinates.
+    /// * symprec: the distance tolerance in Å for merging duplicate sites.
+    ///
+    /// # NOTE
+    /// * The generated atoms will be numbered consecutively from 1, and
+    ///   bonds will not be kept.
+    /// * The site multiplicity is stored in atom properties with key
+    ///   `SITE_MULTIPLICITY_KEY`, and the serial number of the parent atom
+    ///   in asymmetric unit with key `ASYMMETRIC_SITE_KEY`.
+    pub fn expand_by_symmetry(&self, ops: &[SymmetryOperation], symprec: f64) -> Option<Molecule> {
+        if symprec <= 0.0 || ops.is_empty() {
+            return None;
+        }
+        let lat = self.lattice?;
+
+        let mut sites = vec![];
+        let mut atoms: Vec<Atom> = vec![];
+        for (sn, atom) in self.atoms() {
+            let frac = lat.to_frac(atom.position());
+            // the orbit of the site in Cartesian coordinates
+            let mut orbit = vec![];
+            for op in ops {
+                let f = lat.wrap_frac(op.apply(frac));
+                let p = lat.to_cart(f);
+                if !overlapping(&lat, &orbit, p, symprec) {
+                    orbit.push(p);
+                }
+            }
+            if overlapping(&lat, &sites, orbit[0], symprec) {
+                warn!("atom {sn} overlaps with a symmetry equivalent site, ignored.");
+                continue;
+            }
+
+            let multiplicity = orbit.len();
+            for &p in orbit.iter() {
+                let mut a = atom.clone();
+                a.set_position(p);
+                a.properties.store(SITE_MULTIPLICITY_KEY, multiplicity).ok()?;
+                a.properties.store(ASYMMETRIC_SITE_KEY, sn).ok()?;
+                atoms.push(a);
+            }
+            sites.extend(orbit);
+        }
+
+        let mut mol = Molecule::from_atoms(atoms);
+        mol.name = self.name.clone();
+        mol.properties = self.properties.clone();
+        mol.lattice = Some(lat);
+        Some(mol)
+    }
+}
+// 962dc654 ends here
+
+// [[file:../gchemol-core.note::b9c14bf0][b9c14bf0]]
+#[test]
+fn test_expand_by_symmetry() {
+    let op: SymmetryOperation = "'-x+1/2, y-x, z'".parse().unwrap();
+    assert_eq!(op.rotation, [[-1.0, 0.0, 0.0], [-1.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+    assert_eq!(op.translation, [0.5, 0.0, 0.0]);
+    assert!("x, y".parse::<SymmetryOperation>().is_err());
+
+    // space group P-1
+    let ops: Vec<SymmetryOperation> = ["x,y,z", "-x,-y,-z"].iter().map(|s| s.parse().unwrap()).collect();
+    let lat = Lattice::from_params(5.0, 6.0, 7.0, 90.0, 90.0, 90.0);
+    let mut mol = Molecule::from_atoms(vec![Atom::new("Fe", [0.0; 3]), Atom::new("O", [0.5, 1.2, 2.1])]);
+    mol.set_lattice(lat);
+    let expanded = mol.expand_by_symmetry(&ops, 0.01).unwrap();
+    assert_eq!(expanded.natoms(), 3);
+    assert_eq!(expanded.formula(), "FeO2");
+
+    let multiplicity: Vec<usize> = expanded
+        .atoms()
+        .map(|(_, a)| a.properties.load(SITE_MULTIPLICITY_KEY).unwrap())
+        .collect();
+    assert_eq!(multiplicity, vec![1, 2, 2]);
+    let parents: Vec<usize> = expanded.atoms().map(|(_, a)| a.properties.load(ASYMMETRIC_SITE_KEY).unwrap()).collect();
+    assert_eq!(parents, vec![1, 2, 2]);
+    let [x, y, z] = expanded.get_atom(3).unwrap().position();
+    assert!((x - 4.5).abs() < 1e-8 && (y - 4.8).abs() < 1e-8 && (z - 4.9).abs() < 1e-8);
+
+    // invalid parameters
+    assert!(mol.expand_by_symmetry(&ops, 0.0).is_none());
+    assert!(mol.expand_by_symmetry(&ops, -0.01).is_none());
+    assert!(mol.expand_by_symmetry(&[], 0.01).is_none());
+
+    // not a periodic structure
+    mol.unbuild_crystal();
+    assert!(mol.expand_by_symmetry(&ops, 0.01).is_none());
+}
+// b9c14bf0 ends here
@@ -0,0 +1,481 @@
+// [[file:../gchemol-core.note::e241f0dc][e241f0dc]]
+//! Disk-backed storage of trajectory frames in compact binary format
+// e241f0dc ends here
+
+// [[file:../gchemol-core.note::77da4255][77da4255]]
+use crate::common::*;
+use crate::trajectory::{Configuration, Frame, Frames, Trajectory};
+use crate::{Lattice, Molecule};
+
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+// 77da4255 ends here
+
+// [[file:../gchemol-core.note::e6d76d38][e6d76d38]]
+const MAGIC: &[u8; 8] = b"GCHMFRMS";
+const VERSION: u32 = 1;
+
+const HAS_LATTICE: u8 = 1;
+const HAS_VELOCITIES: u8 = 1 << 1;
+const HAS_ENERGY: u8 = 1 << 2;
+const HAS_STEP: u8 = 1 << 3;
+const HAS_TIME: u8 = 1 << 4;
+
+/// Size of the leading flags byte and title length in each record.
+const RECORD_HEADER_SIZE: usize = 5;
+
+/// Size in bytes of a frame record.
+fn record_size(flags: u8, title_len: usize, natoms: usize) -> usize {
+    let mut nvalues = natoms * 3;
+    if flags & HAS_LATTICE != 0 {
+        // three cell vectors and the origin
+        nvalues += 12;
+    }
+    if flags & HAS_VELOCITIES != 0 {
+        nvalues += natoms * 3;
+    }
+    nvalues += [HAS_ENERGY, HAS_STEP, HAS_TIME].iter().filter(|&&f| flags & f != 0).count();
+    RECORD_HEADER_SIZE + title_len + nvalues * 8
+}
+
+fn put_xyz(buf: &mut Vec<u8>, values: &[[f64; 3]]) {
+    for x in values.iter().flatten() {
+        buf.extend_from_slice(&x.to_le_bytes());
+    }
+}
+
+fn encode_record(conf: &Configuration) -> Vec<u8> {
+    let mut flags = 0;
+    let optional = [
+        (conf.lattice().is_some(), HAS_LATTICE),
+        (conf.velocities().is_some(), HAS_VELOCITIES),
+        (conf.energy.is_some(), HAS_ENERGY),
+        (conf.step.is_some(), HAS_STEP),
+        (conf.time.is_some(), HAS_TIME),
+    ];
+    for (present, bit) in optional {
+        if present {
+            flags |= bit;
+        }
+    }
+
+    let title = conf.title.as_bytes();
+    let mut buf = Vec::with_capacity(record_size(flags, title.len(), conf.positions().len()));
+    buf.push(flags);
+    buf.extend_from_slice(&(title.len() as u32).to_le_bytes());
+    buf.extend_from_slice(title);
+    put_xyz(&mut buf, conf.positions());
+    if let Some(lat) = conf.lattice() {
+        let [va, vb, vc] = lat.vectors();
+        let origin = lat.origin();
+        put_xyz(&mut buf, &[va.into(), vb.into(), vc.into(), origin.into()]);
+    }
+    if let Some(velocities) = conf.velocities() {
+        put_xyz(&mut buf, velocities);
+    }
+    if let Some(energy) = conf.energy {
+        buf.extend_from_slice(&energy.to_le_bytes());
+    }
+    if let Some(step) = conf.step {
+        buf.extend_from_slice(&(step as u64).to_le_bytes());
+    }
+    if let Some(time) = conf.time {
+        buf.extend_from_slice(&time.to_le_bytes());
+    }
+    buf
+}
+
+/// A reader consuming bytes from the front of a buffer.
+struct Bytes<'a>(&'a [u8]);
+
+impl Bytes<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        ensure!(self.0.len() >= n, "unexpected end of frame record");
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn xyz(&mut self, n: usize) -> Result<Vec<[f64; 3]>> {
+        (0..n).map(|_| Ok([self.f64()?, self.f64()?, self.f64()?])).collect()
+    }
+}
+
+fn decode_record(buf: &[u8], natoms: usize) -> Result<Configuration> {
+    let mut bytes = Bytes(buf);
+    let flags = bytes.take(1)?[0];
+    let title_len = u32::from_le_bytes(bytes.take(4)?.try_into().unwrap()) as usize;
+    let title = String::from_utf8(bytes.take(title_len)?.to_vec())?;
+    let positions = bytes.xyz(natoms)?;
+    let lattice = if flags & HAS_LATTICE != 0 {
+        let v = bytes.xyz(4)?;
+        let mut lat = Lattice::new([v[0], v[1], v[2]]);
+        lat.set_origin(v[3]);
+        Some(lat)
+    } else {
+        None
+    };
+
+    let mut conf = Configuration::from_positions(positions, lattice);
+    conf.title = title;
+    if flags & HAS_VELOCITIES != 0 {
+        conf.set_velocities(bytes.xyz(natoms)?);
+    }
+    if flags & HAS_ENERGY != 0 {
+        conf.energy = Some(bytes.f64()?);
+    }
+    if flags & HAS_STEP != 0 {
+        conf.step = Some(bytes.u64()? as usize);
+    }
+    if flags & HAS_TIME != 0 {
+        conf.time = Some(bytes.f64()?);
+    }
+    Ok(conf)
+}
+
+/// A trajectory stored on disk, for long simulations with too many
+/// frames to be held in memory. The parent molecule is kept in memory,
+/// and frames are loaded lazily through an index of record offsets.
+///
+/// The file starts with a header containing the parent molecule in
+/// JSON, followed by a binary record for each frame with title,
+/// positions, and optionally lattice, velocities, energy, step and time
+/// in little-endian format.
+///
+/// Frames can be accessed in the same way as `Trajectory`, through
+/// `get`, `iter`, or the [Frames](trajectory/trait.Frames.html) trait
+/// implemented by both. As loading from disk may fail, `try_get` and
+/// `try_iter` report I/O errors instead.
+///
+/// # NOTE
+/// * Bonds, properties and forces of frames are not stored. Loaded
+///   frames share bonds and properties of the parent molecule.
+/// * Unlike `Trajectory`, `slice` and `stride` return `Result`, as
+///   frames have to be loaded from disk into the new trajectory.
+#[derive(Debug)]
+pub struct FrameStore {
+    path: PathBuf,
+    file: RefCell<File>,
+    writable: bool,
+    parent: Molecule,
+    offsets: Vec<u64>,
+    end: u64,
+}
+
+impl FrameStore {
+    /// Create a new store at `path` with `parent` molecule providing atoms
+    /// and other data shared by all frames. Existing file will be
+    /// truncated.
+    pub fn create<P: AsRef<Path>>(path: P, parent: &Molecule) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .with_context(|| format!("failed to create frame store: {path:?}"))?;
+        let json = serde_json::to_vec(parent)?;
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&(json.len() as u64).to_le_bytes());
+        header.extend_from_slice(&json);
+        file.write_all(&header)?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            file: RefCell::new(file),
+            writable: true,
+            parent: parent.clone(),
+            offsets: vec![],
+            end: header.len() as u64,
+        })
+    }
+
+    /// Open an existing store at `path` for reading only. The index of
+    /// frames is built by scanning record headers. An incomplete record
+    /// at the end, such as from an interrupted simulation, is ignored.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("failed to open frame store: {path:?}"))?;
+        Self::from_file(path, file, false)
+    }
+
+    /// Open an existing store at `path` for reading and appending new
+    /// frames. An incomplete record at the end will be discarded.
+    pub fn append<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("failed to open frame store: {path:?}"))?;
+        let store = Self::from_file(path, file, true)?;
+        store.file.borrow().set_len(store.end)?;
+        Ok(store)
+    }
+
+    fn from_file(path: &Path, file: File, writable: bool) -> Result<Self> {
+        let len = file.metadata()?.len();
+        let mut reader = BufReader::new(&file);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic).context("invalid frame store header")?;
+        ensure!(&magic == MAGIC, "not a frame store: {path:?}");
+        let mut buf = [0; 12];
+        reader.read_exact(&mut buf).context("invalid frame store header")?;
+        let version = u32::from_le_bytes(buf[..4].try_into().unwrap());
+        ensure!(version == VERSION, "unsupported frame store version: {version}");
+        let json_len = u64::from_le_bytes(buf[4..].try_into().unwrap()) as usize;
+        let mut json = vec![0; json_len];
+        reader.read_exact(&mut json).context("invalid frame store header")?;
+        let parent: Molecule = serde_json::from_slice(&json)?;
+
+        let natoms = parent.natoms();
+        let mut offsets = vec![];
+        let mut pos = (MAGIC.len() + buf.len() + json_len) as u64;
+        let mut head = [0; RECORD_HEADER_SIZE];
+        while pos + RECORD_HEADER_SIZE as u64 <= len {
+            reader.read_exact(&mut head)?;
+            let title_len = u32::from_le_bytes(head[1..].try_into().unwrap()) as usize;
+            let size = record_size(head[0], title_len, natoms) as u64;
+            if pos + size > len {
+                break;
+            }
+            offsets.push(pos);
+            pos += size;
+            reader.seek_relative((size as usize - RECORD_HEADER_SIZE) as i64)?;
+        }
+        drop(reader);
+
+        Ok(Self {
+            path: path.to_owned(),
+            file: RefCell::new(file),
+            writable,
+            parent,
+            offsets,
+            end: pos,
+        })
+    }
+
+    /// Return the path of the store file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return true if the store has no frames.
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Return the number of frames in the store.
+    pub fn nframes(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Return the number of atoms in each frame.
+    pub fn natoms(&self) -> usize {
+        self.parent.natoms()
+    }
+
+    /// Return the parent molecule shared by all frames.
+    pub fn parent(&self) -> &Molecule {
+        &self.parent
+    }
+
+    /// Append `mol` as a new frame. Return error if `mol` does not match
+    /// atoms and periodicity of the parent molecule.
+    pub fn push(&mut self, mol: &Molecule) -> Result<()> {
+        ensure!(self.parent.matching_configuration(mol), "molecule does not match stored frames");
+        self.push_configuration(&Configuration::from_molecule(mol))
+    }
+
+    /// Append `conf` as a new frame, such as a frame from in-memory
+    /// `Trajectory`.
+    pub fn push_configuration(&mut self, conf: &Configuration) -> Result<()> {
+        ensure!(self.writable, "frame store is opened for reading only: {:?}", self.path);
+        ensure!(conf.positions().len() == self.natoms(), "configuration does not match stored frames");
+        let record = encode_record(conf);
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(self.end))?;
+        file.write_all(&record)?;
+        self.offsets.push(self.end);
+        self.end += record.len() as u64;
+        Ok(())
+    }
+
+    /// Load the configuration of frame `i` from disk.
+    pub fn read_configuration(&self, i: usize) -> Result<Configuration> {
+        ensure!(i < self.nframes(), "frame index out of range: {i}");
+        let start = self.offsets[i];
+        let end = self.offsets.get(i + 1).copied().unwrap_or(self.end);
+        let mut buf = vec![0; (end - start) as usize];
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut buf)?;
+        decode_record(&buf, self.natoms()).with_context(|| format!("invalid record of frame {i}"))
+    }
+
+    /// Load frame `i` from disk. Return error if out of range or failed
+    /// to load.
+    pub fn try_get(&self, i: usize) -> Result<Frame<'_>> {
+        let conf = self.read_configuration(i)?;
+        Ok(Frame::from_owned(&self.parent, conf))
+    }
+
+    /// Return an iterator over frames, loading each frame from disk on
+    /// demand, with errors reported.
+    pub fn try_iter(&self) -> impl Iterator<Item = Result<Frame<'_>>> + '_ {
+        (0..self.nframes()).map(move |i| self.try_get(i))
+    }
+
+    /// Load frame `i` from disk. Return None if out of range or failed
+    /// to load.
+    pub fn get(&self, i: usize) -> Option<Frame<'_>> {
+        self.try_get(i).ok()
+    }
+
+    /// Return an iterator over frames, loading each frame from disk on
+    /// demand. Iteration stops at the first frame failed to load.
+    pub fn iter(&self) -> impl Iterator<Item = Frame<'_>> + '_ {
+        self.try_iter().map_while(|frame| frame.ok())
+    }
+
+    /// Load frames in `range` into a new in-memory trajectory.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Result<Trajectory> {
+        let start = match range.start_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&i) => i + 1,
+            Bound::Excluded(&i) => i,
+            Bound::Unbounded => self.nframes(),
+        };
+        ensure!(start <= end && end <= self.nframes(), "frame range out of bounds: {start}..{end}");
+        self.load((start..end).collect())
+    }
+
+    /// Load every `step`-th frame, starting from the first, into a new
+    /// in-memory trajectory.
+    pub fn stride(&self, step: usize) -> Result<Trajectory> {
+        ensure!(step > 0, "invalid step: {step}");
+        self.load((0..self.nframes()).step_by(step).collect())
+    }
+
+    /// Load all frames into a new in-memory trajectory.
+    pub fn to_trajectory(&self) -> Result<Trajectory> {
+        self.slice(..)
+    }
+
+    fn load(&self, indices: Vec<usize>) -> Result<Trajectory> {
+        let mut traj = Trajectory::new(self.parent.clone());
+        traj.frames = indices.into_iter().map(|i| self.read_configuration(i)).collect::<Result<_>>()?;
+        Ok(traj)
+    }
+}
+
+impl Frames for FrameStore {
+    fn nframes(&self) -> usize {
+        self.nframes()
+    }
+
+    fn parent(&self) -> &Molecule {
+        self.parent()
+    }
+
+    fn get(&self, i: usize) -> Option<Frame<'_>> {
+        self.get(i)
+    }
+
+    fn frames(&self) -> impl Iterator<Item = Frame<'_>> + '_ {
+        self.iter()
+    }
+}
+// e6d76d38 ends here
+
+// [[file:../gchemol-core.note::3e543fab][3e543fab]]
+#[test]
+fn test_frame_store() {
+    use crate::Atom;
+    use std::convert::TryFrom;
+
+    let mols: Vec<_> = (0..5)
+        .map(|i| {
+            let x = i as f64 * 0.1;
+            let mut mol = Molecule::from_atoms(vec![Atom::new("O", [x, 0.0, 0.0]), Atom::new("H", [x + 0.96, 0.0, 0.0])]);
+            mol.set_title(format!("frame {i}"));
+            mol.set_lattice(Lattice::from_params(10.0 + x, 10.0, 10.0, 90.0, 90.0, 90.0));
+            mol
+        })
+        .collect();
+    let mut traj = Trajectory::try_from(mols.clone()).unwrap();
+    traj.frames[1].set_velocities(vec![[0.1, 0.2, 0.3], [0.0, 0.0, -1.0]]);
+    traj.frames[2].energy = Some(-1.5);
+    traj.frames[2].step = Some(20);
+    traj.frames[3].time = Some(1.5);
+
+    let path = std::env::temp_dir().join(format!("gchemol-frame-store-{}.bin", std::process::id()));
+    let mut store = FrameStore::create(&path, traj.parent()).unwrap();
+    for frame in traj.iter() {
+        store.push_configuration(&frame).unwrap();
+    }
+    assert_eq!(store.nframes(), 5);
+
+    // random access after reopening
+    let store = FrameStore::open(&path).unwrap();
+    assert_eq!(store.nframes(), 5);
+    assert_eq!(store.natoms(), 2);
+    let frame = store.try_get(1).unwrap();
+    assert_eq!(frame.title, "frame 1");
+    assert_eq!(frame.velocities(), traj[1].velocities());
+    assert_eq!(frame.positions(), traj[1].positions());
+    assert_eq!(store.get(2).unwrap().energy, Some(-1.5));
+    assert_eq!(store.get(2).unwrap().step, Some(20));
+    assert_eq!(store.get(3).unwrap().time, Some(1.5));
+    assert_eq!(store.get(4).unwrap().lattice().unwrap().lengths(), traj[4].lattice().unwrap().lengths());
+    assert!(store.get(5).is_none());
+    assert!(store.try_get(5).is_err());
+    let mol = store.get(3).unwrap().to_molecule();
+    assert_eq!(mol.title(), "frame 3");
+    assert!(store.parent().matching_configuration(&mol));
+
+    // lazy iteration and loading into memory
+    let titles: Vec<_> = store.try_iter().map(|frame| frame.unwrap().title.clone()).collect();
+    assert_eq!(titles[4], "frame 4");
+    assert_eq!(store.iter().count(), 5);
+
+    // the same code works with both in-memory and disk-backed frames
+    fn last_title<T: Frames>(frames: &T) -> Option<String> {
+        frames.get(frames.nframes().checked_sub(1)?).map(|frame| frame.title.clone())
+    }
+    assert_eq!(last_title(&store), last_title(&traj));
+    assert_eq!(store.frames().count(), Frames::frames(&traj).count());
+    assert_eq!(store.slice(1..3).unwrap().nframes(), 2);
+    assert_eq!(store.stride(2).unwrap().nframes(), 3);
+    assert!(store.slice(3..9).is_err());
+
+    // appending after an interrupted write
+    let mut store = FrameStore::open(&path).unwrap();
+    assert!(store.push(&mols[0]).is_err());
+    std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&[1, 2, 3, 4, 5, 6]).unwrap();
+    let mut store = FrameStore::append(&path).unwrap();
+    assert_eq!(store.nframes(), 5);
+    store.push(&mols[0]).unwrap();
+    let traj = FrameStore::open(&path).unwrap().to_trajectory().unwrap();
+    assert_eq!(traj.nframes(), 6);
+    assert_eq!(traj[5].title, "frame 0");
+    std::fs::remove_file(&path).unwrap();
+}
+// 3e543fab ends here
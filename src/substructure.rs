@@ -0,0 +1,730 @@
+// [[file:../gchemol-core.note::138cda4a][138cda4a]]
+//! Substructure search in `Molecule` using a subset of SMARTS.
+//!
+//! # NOTE
+//! * The VF2 algorithm of petgraph (`subgraph_isomorphisms_iter`) was
+//!   evaluated but not used: it only finds induced subgraphs, so a
+//!   matched set of atoms cannot have bonds missing in the query, such
+//!   as `C-C-C` in cyclopropane. SMARTS requires non-induced matching,
+//!   which is done here by a simple backtracking search.
+// 138cda4a ends here
+
+// [[file:../gchemol-core.note::51cefa01][51cefa01]]
+use crate::common::*;
+use crate::element::ELEMENTS;
+use crate::{Atom, Bond, BondKind, Molecule};
+
+use gchemol_graph::petgraph::graph::{NodeIndex as QueryIndex, UnGraph};
+use gchemol_graph::petgraph::visit::EdgeRef;
+use std::collections::{HashMap, HashSet};
+// 51cefa01 ends here
+
+// [[file:../gchemol-core.note::5cf47bab][5cf47bab]]
+/// A logical expression over query primitives.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr<P> {
+    Prim(P),
+    Not(Box<Expr<P>>),
+    And(Box<Expr<P>>, Box<Expr<P>>),
+    Or(Box<Expr<P>>, Box<Expr<P>>),
+}
+
+impl<P> Expr<P> {
+    fn eval(&self, f: &impl Fn(&P) -> bool) -> bool {
+        match self {
+            Expr::Prim(p) => f(p),
+            Expr::Not(e) => !e.eval(f),
+            Expr::And(a, b) => a.eval(f) && b.eval(f),
+            Expr::Or(a, b) => a.eval(f) || b.eval(f),
+        }
+    }
+}
+
+/// Atom primitives
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AtomPrimitive {
+    /// `*`: any atom
+    Any,
+    /// `a`: atom with aromatic bonds
+    Aromatic,
+    /// `A`: atom without aromatic bonds
+    Aliphatic,
+    /// `#n`: atomic number
+    Number(usize),
+    /// Element symbol: uppercase for aliphatic and lowercase for aromatic atom
+    Element(usize, bool),
+    /// `Hn`: number of bonded hydrogen atoms
+    HCount(usize),
+    /// `Dn` or `Xn`: number of bonded atoms
+    Degree(usize),
+    /// `R`: in ring or not (`R0`)
+    Ring(bool),
+}
+
+/// Bond primitives
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BondPrimitive {
+    /// `~`
+    Any,
+    /// `-`, `=`, `#`, `:`
+    Kind(BondKind),
+    /// `@`
+    Ring,
+}
+
+type AtomExpr = Expr<AtomPrimitive>;
+type BondExpr = Expr<BondPrimitive>;
+
+/// The implicit bond in SMARTS: single or aromatic.
+fn default_bond() -> BondExpr {
+    let single = Expr::Prim(BondPrimitive::Kind(BondKind::Single));
+    let aromatic = Expr::Prim(BondPrimitive::Kind(BondKind::Aromatic));
+    Expr::Or(Box::new(single), Box::new(aromatic))
+}
+// 5cf47bab ends here
+
+// [[file:../gchemol-core.note::38816944][38816944]]
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    source: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            chars: source.chars().collect(),
+            pos: 0,
+            source,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Read an optional unsigned integer.
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if self.pos > start {
+            let s: String = self.chars[start..self.pos].iter().collect();
+            s.parse().ok()
+        } else {
+            None
+        }
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        format_err!("{msg} at position {} in query {:?}", self.pos, self.source)
+    }
+
+    /// expr := or (';' or)*
+    fn expr<P>(&mut self, prim: fn(&mut Self) -> Result<Option<P>>) -> Result<Expr<P>> {
+        let mut e = self.or_expr(prim)?;
+        while self.eat(';') {
+            e = Expr::And(Box::new(e), Box::new(self.or_expr(prim)?));
+        }
+        Ok(e)
+    }
+
+    /// or := and (',' and)*
+    fn or_expr<P>(&mut self, prim: fn(&mut Self) -> Result<Option<P>>) -> Result<Expr<P>> {
+        let mut e = self.and_expr(prim)?;
+        while self.eat(',') {
+            e = Expr::Or(Box::new(e), Box::new(self.and_expr(prim)?));
+        }
+        Ok(e)
+    }
+
+    /// and := unary ('&'? unary)*
+    fn and_expr<P>(&mut self, prim: fn(&mut Self) -> Result<Option<P>>) -> Result<Expr<P>> {
+        let mut e = self.unary(prim)?.ok_or_else(|| self.error("expect a primitive"))?;
+        loop {
+            let rhs = if self.eat('&') {
+                self.unary(prim)?.ok_or_else(|| self.error("expect a primitive"))?
+            } else if let Some(rhs) = self.unary(prim)? {
+                rhs
+            } else {
+                break;
+            };
+            e = Expr::And(Box::new(e), Box::new(rhs));
+        }
+        Ok(e)
+    }
+
+    /// unary := '!' unary | primitive
+    fn unary<P>(&mut self, prim: fn(&mut Self) -> Result<Option<P>>) -> Result<Option<Expr<P>>> {
+        if self.eat('!') {
+            let e = self.unary(prim)?.ok_or_else(|| self.error("expect a primitive after `!`"))?;
+            return Ok(Some(Expr::Not(Box::new(e))));
+        }
+        Ok(prim(self)?.map(Expr::Prim))
+    }
+
+    /// Parse one atom primitive in bracket. Return None if reaching the
+    /// closing bracket.
+    fn atom_primitive(&mut self) -> Result<Option<AtomPrimitive>> {
+        use AtomPrimitive::*;
+
+        let c = match self.peek() {
+            None => bail!(self.error("unclosed bracket atom")),
+            Some(']' | ',' | ';' | '&') => return Ok(None),
+            Some(c) => c,
+        };
+        // two-letter element symbol takes precedence, e.g. Hg, Rb, Al
+        if let Some(d) = self.peek_at(1).filter(|d| c.is_ascii_uppercase() && d.is_ascii_lowercase()) {
+            if let Some(&n) = ELEMENTS.get(format!("{c}{d}").as_str()) {
+                self.pos += 2;
+                return Ok(Some(Element(n, false)));
+            }
+        }
+        self.pos += 1;
+        let prim = match c {
+            '*' => Any,
+            'a' => Aromatic,
+            'A' => Aliphatic,
+            '#' => Number(self.number().ok_or_else(|| self.error("expect atomic number"))?),
+            'H' => HCount(self.number().unwrap_or(1)),
+            'D' | 'X' => Degree(self.number().unwrap_or(1)),
+            'R' => Ring(self.number() != Some(0)),
+            'b' | 'c' | 'n' | 'o' | 'p' | 's' => Element(ELEMENTS[c.to_ascii_uppercase().to_string().as_str()], true),
+            _ => {
+                if let Some(&n) = ELEMENTS.get(c.to_string().as_str()) {
+                    Element(n, false)
+                } else {
+                    self.pos -= 1;
+                    bail!(self.error("unsupported atom primitive"));
+                }
+            }
+        };
+        Ok(Some(prim))
+    }
+
+    /// Parse one bond primitive. Return None if no bond primitive
+    /// found.
+    fn bond_primitive(&mut self) -> Result<Option<BondPrimitive>> {
+        use BondPrimitive::*;
+
+        let prim = match self.peek() {
+            Some('~') => Any,
+            Some('-') => Kind(BondKind::Single),
+            Some('=') => Kind(BondKind::Double),
+            Some('#') => Kind(BondKind::Triple),
+            Some(':') => Kind(BondKind::Aromatic),
+            Some('@') => Ring,
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        Ok(Some(prim))
+    }
+
+    /// Parse an atom in bracket or in the organic subset.
+    fn atom(&mut self) -> Result<AtomExpr> {
+        use AtomPrimitive::*;
+
+        if self.eat('[') {
+            // [H] is a hydrogen atom, not an atom with one hydrogen
+            if self.peek() == Some('H') && self.peek_at(1) == Some(']') {
+                self.pos += 2;
+                return Ok(Expr::Prim(Element(1, false)));
+            }
+            let e = self.expr(Self::atom_primitive)?;
+            ensure!(self.eat(']'), self.error("expect `]`"));
+            return Ok(e);
+        }
+
+        let c = self.peek().ok_or_else(|| self.error("expect an atom"))?;
+        if c == '*' {
+            self.pos += 1;
+            return Ok(Expr::Prim(Any));
+        }
+        for (sym, aromatic) in [("Cl", false), ("Br", false)] {
+            if self.chars[self.pos..].starts_with(&sym.chars().collect_vec()) {
+                self.pos += 2;
+                return Ok(Expr::Prim(Element(ELEMENTS[sym], aromatic)));
+            }
+        }
+        let prim = match c {
+            'B' | 'C' | 'N' | 'O' | 'P' | 'S' | 'F' | 'I' => Element(ELEMENTS[c.to_string().as_str()], false),
+            'b' | 'c' | 'n' | 'o' | 'p' | 's' => Element(ELEMENTS[c.to_ascii_uppercase().to_string().as_str()], true),
+            _ => bail!(self.error("unexpected character")),
+        };
+        self.pos += 1;
+        Ok(Expr::Prim(prim))
+    }
+
+    /// Parse the whole query into a graph.
+    fn parse(&mut self) -> Result<UnGraph<AtomExpr, BondExpr>> {
+        let mut graph = UnGraph::default();
+        // the atom to be bonded with the next atom
+        let mut prev: Option<QueryIndex> = None;
+        // the pending bond between `prev` and the next atom
+        let mut bond: Option<BondExpr> = None;
+        let mut branches = vec![];
+        let mut ring_bonds: HashMap<usize, (QueryIndex, Option<BondExpr>)> = HashMap::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                '(' => {
+                    let p = prev.ok_or_else(|| self.error("unexpected branch"))?;
+                    ensure!(bond.is_none(), self.error("unexpected branch after bond"));
+                    branches.push(p);
+                    self.pos += 1;
+                }
+                ')' => {
+                    ensure!(bond.is_none(), self.error("dangling bond"));
+                    prev = Some(branches.pop().ok_or_else(|| self.error("unmatched `)`"))?);
+                    self.pos += 1;
+                }
+                '.' => {
+                    ensure!(bond.is_none() && branches.is_empty(), self.error("unexpected `.`"));
+                    prev = None;
+                    self.pos += 1;
+                }
+                '0'..='9' | '%' => {
+                    let p = prev.ok_or_else(|| self.error("unexpected ring closure"))?;
+                    let digit = if self.eat('%') {
+                        ensure!(self.peek_at(1).is_some_and(|c| c.is_ascii_digit()), self.error("expect two digits after `%`"));
+                        let s: String = self.chars[self.pos..self.pos + 2].iter().collect();
+                        self.pos += 2;
+                        s.parse()?
+                    } else {
+                        self.pos += 1;
+                        c.to_digit(10).unwrap() as usize
+                    };
+                    if let Some((q, b)) = ring_bonds.remove(&digit) {
+                        ensure!(q != p && graph.find_edge(p, q).is_none(), self.error("invalid ring closure"));
+                        let b = match (bond.take(), b) {
+                            (Some(b1), Some(b2)) => {
+                                ensure!(b1 == b2, self.error("conflicting ring closure bonds"));
+                                b1
+                            }
+                            (b1, b2) => b1.or(b2).unwrap_or_else(default_bond),
+                        };
+                        graph.add_edge(p, q, b);
+                    } else {
+                        ring_bonds.insert(digit, (p, bond.take()));
+                    }
+                }
+                '~' | '-' | '=' | '#' | ':' | '@' | '!' => {
+                    ensure!(prev.is_some() && bond.is_none(), self.error("unexpected bond"));
+                    bond = Some(self.expr(Self::bond_primitive)?);
+                }
+                _ => {
+                    let atom = self.atom()?;
+                    let node = graph.add_node(atom);
+                    if let Some(p) = prev {
+                        graph.add_edge(p, node, bond.take().unwrap_or_else(default_bond));
+                    }
+                    prev = Some(node);
+                }
+            }
+        }
+        ensure!(graph.node_count() > 0, "empty query");
+        ensure!(bond.is_none(), self.error("dangling bond"));
+        ensure!(branches.is_empty(), self.error("unclosed branch"));
+        ensure!(ring_bonds.is_empty(), self.error("unclosed ring"));
+
+        Ok(graph)
+    }
+}
+// 38816944 ends here
+
+// [[file:../gchemol-core.note::55439b40][55439b40]]
+/// A substructure query parsed from a subset of SMARTS, for use in
+/// [find_substructures](struct.Molecule.html#method.find_substructures).
+///
+/// # Supported syntax
+/// * atoms: organic subset (`C`, `c`, `Cl`, ...), `*`, and bracket atoms
+///   with primitives: element symbol, `#n` (atomic number), `Hn`
+///   (bonded hydrogens), `Dn` or `Xn` (bonded atoms), `R`/`R0` (in ring
+///   or not), `a` (aromatic) and `A` (aliphatic).
+/// * bonds: `-`, `=`, `#`, `:`, `~` (any) and `@` (ring bond). An
+///   implicit bond means single or aromatic.
+/// * logical operators: `!`, `&`, `,` and `;`, in the SMARTS precedence.
+/// * branches `()`, ring closures (`1`, `%12`), and disconnected
+///   components by `.`
+///
+/// # Example
+/// ```ignore
+/// // carboxylic acid
+/// let query: SubstructureQuery = "[CX3](=O)[OX2H1]".parse()?;
+/// // metal-O-H motif
+/// let query: SubstructureQuery = "[Fe,Co,Ni]O[H]".parse()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct SubstructureQuery {
+    graph: UnGraph<AtomExpr, BondExpr>,
+}
+
+impl SubstructureQuery {
+    /// Return the number of atoms in query.
+    pub fn natoms(&self) -> usize {
+        self.graph.node_count()
+    }
+}
+
+impl FromStr for SubstructureQuery {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let graph = Parser::new(s.trim()).parse()?;
+        Ok(Self { graph })
+    }
+}
+// 55439b40 ends here
+
+// [[file:../gchemol-core.note::0e79d58f][0e79d58f]]
+/// Atom attributes for query matching
+#[derive(Debug, Clone, Copy)]
+struct AtomInfo {
+    number: usize,
+    degree: usize,
+    hcount: usize,
+    aromatic: bool,
+    ring: bool,
+}
+
+impl AtomInfo {
+    fn matches(&self, p: &AtomPrimitive) -> bool {
+        use AtomPrimitive::*;
+
+        match *p {
+            Any => true,
+            Aromatic => self.aromatic,
+            Aliphatic => !self.aromatic,
+            Number(n) => self.number == n,
+            Element(n, aromatic) => self.number == n && self.aromatic == aromatic,
+            HCount(n) => self.hcount == n,
+            Degree(n) => self.degree == n,
+            Ring(ring) => self.ring == ring,
+        }
+    }
+}
+
+/// Bond attributes for query matching
+#[derive(Debug, Clone, Copy)]
+struct BondInfo {
+    kind: BondKind,
+    ring: bool,
+}
+
+impl BondInfo {
+    fn matches(&self, p: &BondPrimitive) -> bool {
+        match *p {
+            BondPrimitive::Any => true,
+            BondPrimitive::Kind(k) => self.kind == k,
+            BondPrimitive::Ring => self.ring,
+        }
+    }
+}
+
+/// Find bonds in rings, i.e. bonds that are not bridges, indexed by
+/// edge index in `graph`.
+pub(crate) fn find_ring_bonds<N, E>(graph: &UnGraph<N, E>) -> Vec<bool> {
+    let n = graph.node_count();
+    let mut adjacency = vec![vec![]; n];
+    for e in graph.edge_references() {
+        let (i, j) = (e.source().index(), e.target().index());
+        adjacency[i].push((j, e.id().index()));
+        adjacency[j].push((i, e.id().index()));
+    }
+
+    // Tarjan's bridge finding with an explicit stack
+    let mut ring = vec![true; graph.edge_count()];
+    let mut disc = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut time = 0;
+    for root in 0..n {
+        if disc[root] != usize::MAX {
+            continue;
+        }
+        disc[root] = time;
+        low[root] = time;
+        time += 1;
+        // node, the edge to its parent, the next neighbor to visit
+        let mut stack: Vec<(usize, Option<usize>, usize)> = vec![(root, None, 0)];
+        while let Some(&(u, parent_edge, k)) = stack.last() {
+            if let Some(&(v, e)) = adjacency[u].get(k) {
+                stack.last_mut().unwrap().2 += 1;
+                if Some(e) == parent_edge {
+                    continue;
+                }
+                if disc[v] == usize::MAX {
+                    disc[v] = time;
+                    low[v] = time;
+                    time += 1;
+                    stack.push((v, Some(e), 0));
+                } else {
+                    low[u] = low[u].min(disc[v]);
+                }
+            } else {
+                stack.pop();
+                if let (Some(e), Some(&(p, _, _))) = (parent_edge, stack.last()) {
+                    low[p] = low[p].min(low[u]);
+                    if low[u] > disc[p] {
+                        ring[e] = false;
+                    }
+                }
+            }
+        }
+    }
+
+    ring
+}
+
+/// Build the graph for matching, together with the atom serial number
+/// of each node.
+fn target_graph(mol: &Molecule) -> (UnGraph<AtomInfo, BondInfo>, Vec<usize>) {
+    let (graph, numbers) = mol.compact_graph();
+    let ring = find_ring_bonds(&graph);
+    let graph = graph.map(
+        |n, atom: &&Atom| {
+            let mut info = AtomInfo {
+                number: atom.number(),
+                degree: 0,
+                hcount: 0,
+                aromatic: false,
+                ring: false,
+            };
+            for e in graph.edges(n) {
+                info.degree += 1;
+                info.aromatic |= e.weight().kind() == BondKind::Aromatic;
+                info.ring |= ring[e.id().index()];
+                let other = if e.source() == n { e.target() } else { e.source() };
+                if graph[other].number() == 1 {
+                    info.hcount += 1;
+                }
+            }
+            info
+        },
+        |e, bond: &&Bond| BondInfo {
+            kind: bond.kind(),
+            ring: ring[e.index()],
+        },
+    );
+
+    (graph, numbers)
+}
+
+/// Backtracking search for non-induced subgraph matches
+/// (monomorphisms): each query bond must map onto a target bond, while
+/// extra bonds between matched atoms in the target are ignored.
+struct Matcher<'a> {
+    query: &'a UnGraph<AtomExpr, BondExpr>,
+    graph: &'a UnGraph<AtomInfo, BondInfo>,
+    order: Vec<QueryIndex>,
+    mapping: Vec<Option<QueryIndex>>,
+    used: Vec<bool>,
+    found: Vec<Vec<usize>>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(query: &'a UnGraph<AtomExpr, BondExpr>, graph: &'a UnGraph<AtomInfo, BondInfo>) -> Self {
+        // visit query atoms in breadth-first order, so each atom except
+        // the first in a fragment is bonded to a visited one
+        let mut order = vec![];
+        let mut seen = vec![false; query.node_count()];
+        for root in query.node_indices() {
+            if seen[root.index()] {
+                continue;
+            }
+            seen[root.index()] = true;
+            let mut queue = std::collections::VecDeque::from([root]);
+            while let Some(q) = queue.pop_front() {
+                order.push(q);
+                for p in query.neighbors(q) {
+                    if !seen[p.index()] {
+                        seen[p.index()] = true;
+                        queue.push_back(p);
+                    }
+                }
+            }
+        }
+        Self {
+            query,
+            graph,
+            order,
+            mapping: vec![None; query.node_count()],
+            used: vec![false; graph.node_count()],
+            found: vec![],
+        }
+    }
+
+    /// Test if query atom `q` can be mapped onto target atom `t`.
+    fn feasible(&self, q: QueryIndex, t: QueryIndex) -> bool {
+        if self.used[t.index()] || !self.query[q].eval(&|p| self.graph[t].matches(p)) {
+            return false;
+        }
+        self.query.edges(q).all(|e| {
+            let other = if e.source() == q { e.target() } else { e.source() };
+            match self.mapping[other.index()] {
+                Some(u) => self
+                    .graph
+                    .find_edge(t, u)
+                    .is_some_and(|te| e.weight().eval(&|p| self.graph[te].matches(p))),
+                None => true,
+            }
+        })
+    }
+
+    fn extend(&mut self, depth: usize) {
+        let Some(&q) = self.order.get(depth) else {
+            self.found.push(self.mapping.iter().map(|t| t.unwrap().index()).collect());
+            return;
+        };
+        let anchor = self.query.neighbors(q).find_map(|p| self.mapping[p.index()]);
+        let candidates: Vec<_> = match anchor {
+            Some(u) => self.graph.neighbors(u).collect(),
+            None => self.graph.node_indices().collect(),
+        };
+        for t in candidates {
+            if self.feasible(q, t) {
+                self.mapping[q.index()] = Some(t);
+                self.used[t.index()] = true;
+                self.extend(depth + 1);
+                self.used[t.index()] = false;
+                self.mapping[q.index()] = None;
+            }
+        }
+    }
+}
+
+impl Molecule {
+    /// Find all substructures matching `query`. Return the atom serial
+    /// numbers for each match, in the same order as atoms in `query`.
+    /// Matches covering the same atoms and bonds are reported only once.
+    ///
+    /// # NOTE
+    /// * Hydrogen atoms are explicit in `Molecule`, so `H` counts and
+    ///   degrees refer to bonded atoms in the graph.
+    /// * Matched atoms may have extra bonds among themselves that are
+    ///   missing in the query, as in SMARTS matching.
+    /// * Bond kinds are taken as they are: molecules bonded by `rebond`
+    ///   only have single bonds.
+    pub fn find_substructures(&self, query: &SubstructureQuery) -> Vec<Vec<usize>> {
+        let (graph, numbers) = target_graph(self);
+        let mut matcher = Matcher::new(&query.graph, &graph);
+        matcher.extend(0);
+
+        // the same atoms and bonds matched in different order
+        let mut found = HashSet::new();
+        let mut matches = vec![];
+        for m in matcher.found {
+            let sns: Vec<_> = m.into_iter().map(|i| numbers[i]).collect();
+            let atoms: Vec<_> = sns.iter().copied().sorted().collect();
+            let bonds: Vec<_> = query
+                .graph
+                .edge_references()
+                .map(|e| {
+                    let (i, j) = (sns[e.source().index()], sns[e.target().index()]);
+                    (i.min(j), i.max(j))
+                })
+                .sorted()
+                .collect();
+            if found.insert((atoms, bonds)) {
+                matches.push(sns);
+            }
+        }
+        matches.sort();
+
+        matches
+    }
+}
+// 0e79d58f ends here
+
+// [[file:../gchemol-core.note::f1819f5d][f1819f5d]]
+#[test]
+fn test_find_substructures() {
+    // acetic acid with explicit hydrogens
+    let atoms = [
+        ("C", [0.0, 0.0, 0.0]),
+        ("C", [1.5, 0.0, 0.0]),
+        ("O", [2.1, 1.1, 0.0]),
+        ("O", [2.1, -1.1, 0.0]),
+        ("H", [3.0, -1.1, 0.0]),
+        ("H", [-0.4, 1.0, 0.0]),
+        ("H", [-0.4, -0.5, 0.9]),
+        ("H", [-0.4, -0.5, -0.9]),
+    ];
+    let mut mol = Molecule::from_atoms(atoms.iter().map(|&(s, p)| Atom::new(s, p)));
+    mol.add_bond(1, 2, Bond::single());
+    mol.add_bond(2, 3, Bond::double());
+    mol.add_bond(2, 4, Bond::single());
+    mol.add_bond(4, 5, Bond::single());
+    for h in 6..=8 {
+        mol.add_bond(1, h, Bond::single());
+    }
+
+    let query: SubstructureQuery = "[CX3](=O)[OX2H1]".parse().unwrap();
+    assert_eq!(query.natoms(), 3);
+    assert_eq!(mol.find_substructures(&query), vec![vec![2, 3, 4]]);
+    // the methyl group: matched only once in spite of symmetry
+    let query: SubstructureQuery = "[CH3]([H])([H])[H]".parse().unwrap();
+    assert_eq!(mol.find_substructures(&query).len(), 1);
+    let query: SubstructureQuery = "C~O".parse().unwrap();
+    assert_eq!(mol.find_substructures(&query), vec![vec![2, 3], vec![2, 4]]);
+    let query: SubstructureQuery = "[#6;!H3]-[O;R]".parse().unwrap();
+    assert!(mol.find_substructures(&query).is_empty());
+
+    // metal-O-H motif
+    let mut mol = Molecule::from_atoms(vec![
+        Atom::new("Fe", [0.0, 0.0, 0.0]),
+        Atom::new("O", [1.8, 0.0, 0.0]),
+        Atom::new("H", [2.8, 0.0, 0.0]),
+        Atom::new("O", [-1.8, 0.0, 0.0]),
+    ]);
+    mol.add_bond(1, 2, Bond::single());
+    mol.add_bond(2, 3, Bond::single());
+    mol.add_bond(1, 4, Bond::single());
+    let query: SubstructureQuery = "[Fe,Co,Ni]O[H]".parse().unwrap();
+    assert_eq!(mol.find_substructures(&query), vec![vec![1, 2, 3]]);
+
+    // ring bonds
+    let mut mol = Molecule::from_atoms((0..6).map(|i| Atom::new("C", [i as f64, 0.0, 0.0])));
+    for i in 1..6 {
+        mol.add_bond(i, i + 1, Bond::aromatic());
+    }
+    mol.add_bond(6, 1, Bond::aromatic());
+    let query: SubstructureQuery = "c1ccccc1".parse().unwrap();
+    assert_eq!(mol.find_substructures(&query).len(), 1);
+    let query: SubstructureQuery = "[c;R]@c".parse().unwrap();
+    assert_eq!(mol.find_substructures(&query).len(), 6);
+
+    // chains inside a small ring, with extra bonds between matched atoms
+    let mut mol = Molecule::from_atoms((0..3).map(|i| Atom::new("C", [i as f64, 0.0, 0.0])));
+    mol.add_bond(1, 2, Bond::single());
+    mol.add_bond(2, 3, Bond::single());
+    mol.add_bond(3, 1, Bond::single());
+    let query: SubstructureQuery = "CCC".parse().unwrap();
+    assert_eq!(mol.find_substructures(&query), vec![vec![1, 2, 3], vec![1, 3, 2], vec![2, 1, 3]]);
+    let query: SubstructureQuery = "CC".parse().unwrap();
+    assert_eq!(mol.find_substructures(&query).len(), 3);
+    // the bond between terminal atoms is missing in the query, which
+    // an induced subgraph search, such as VF2 in petgraph, rejects
+    let query: SubstructureQuery = "C-C-C".parse().unwrap();
+    assert_eq!(mol.find_substructures(&query).len(), 3);
+    assert!(mol.find_substructures(&query).iter().all(|m| mol.get_bond(m[0], m[2]).is_some()));
+
+    // invalid queries
+    for s in ["", "C(", "C1CC", "C=", "[C", "C)C", "[Xx]"] {
+        assert!(s.parse::<SubstructureQuery>().is_err(), "{s:?}");
+    }
+}
+// f1819f5d ends here
@@ -0,0 +1,239 @@
+// [[file:../gchemol-core.note::e42f91f3][e42f91f3]]
+//! Superposition of trajectory frames with RMSD and RMSF analysis
+// e42f91f3 ends here
+
+// [[file:../gchemol-core.note::d7c8b266][d7c8b266]]
+use crate::common::*;
+use crate::trajectory::Trajectory;
+use crate::Molecule;
+
+use gchemol_geometry::{Superimpose, Superposition};
+// d7c8b266 ends here
+
+// [[file:../gchemol-core.note::279f0f45][279f0f45]]
+/// The reference structure for trajectory alignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlignReference {
+    /// The frame with this index.
+    Frame(usize),
+    /// The average structure, refined iteratively starting from the
+    /// first frame.
+    Average,
+}
+
+/// The result of trajectory alignment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrajectoryAlignment {
+    /// Superposition RMSD of each frame against the reference.
+    pub rmsd: Vec<f64>,
+
+    /// Root mean square fluctuation of each atom around its average
+    /// position, in serial number order.
+    pub rmsf: Vec<f64>,
+
+    /// The average structure of aligned frames.
+    pub average: Molecule,
+}
+
+const MAX_AVERAGE_ITERATIONS: usize = 20;
+const AVERAGE_CONVERGENCE: f64 = 1e-6;
+
+fn average_positions(traj: &Trajectory) -> Vec<[f64; 3]> {
+    let n = traj.nframes() as f64;
+    let mut average = vec![[0.0; 3]; traj.natoms()];
+    for frame in traj.iter() {
+        for (a, p) in average.iter_mut().zip(frame.positions()) {
+            (0..3).for_each(|k| a[k] += p[k] / n);
+        }
+    }
+    average
+}
+
+fn squared_distance(p: &[f64; 3], q: &[f64; 3]) -> f64 {
+    (0..3).map(|k| (p[k] - q[k]).powi(2)).sum()
+}
+
+/// Superposition of `positions` onto `reference` using atoms `indices`,
+/// with proper rotations only.
+fn superpose(positions: &[[f64; 3]], reference: &[[f64; 3]], indices: &[usize], weights: &[f64]) -> Superposition {
+    let this: Vec<_> = indices.iter().map(|&i| positions[i]).collect();
+    let prev: Vec<_> = indices.iter().map(|&i| reference[i]).collect();
+    Superimpose::new(&this).onto(&prev, Some(weights))
+}
+
+impl Trajectory {
+    /// Superimpose all frames onto the reference structure in place,
+    /// using atoms in `selection` (serial numbers) or all atoms for
+    /// fitting. Return per-frame RMSD against the reference, per-atom
+    /// RMSF and the average structure.
+    ///
+    /// # NOTE
+    /// * Frames are aligned with atomic masses as weights, using proper
+    ///   rotations only: frames are never mirrored, so chirality is
+    ///   preserved.
+    /// * For the average reference, RMSD is computed against the
+    ///   returned average structure.
+    /// * Positions are not unwrapped across periodic boundaries.
+    pub fn superimpose_frames(&mut self, reference: AlignReference, selection: Option<&[usize]>) -> Result<TrajectoryAlignment> {
+        ensure!(!self.is_empty(), "no frames to align");
+        let numbers: Vec<_> = self.parent().numbers().collect();
+        let indices: Vec<usize> = match selection {
+            Some(selected) => {
+                ensure!(!selected.is_empty(), "empty selection for alignment");
+                selected
+                    .iter()
+                    .map(|sn| {
+                        numbers
+                            .iter()
+                            .position(|x| x == sn)
+                            .ok_or_else(|| format_err!("invalid atom sn in selection: {sn}"))
+                    })
+                    .collect::<Result<_>>()?
+            }
+            None => (0..numbers.len()).collect(),
+        };
+        let masses: Vec<_> = self.parent().masses().collect();
+        let weights: Vec<_> = indices.iter().map(|&i| masses[i]).collect();
+        let (first, niter) = match reference {
+            AlignReference::Frame(i) => {
+                ensure!(i < self.nframes(), "invalid reference frame: {i}");
+                (i, 1)
+            }
+            AlignReference::Average => (0, MAX_AVERAGE_ITERATIONS),
+        };
+
+        let mut positions_ref = self.frames[first].positions().to_vec();
+        let mut rmsd = vec![];
+        for _ in 0..niter {
+            rmsd.clear();
+            for conf in self.frames.iter_mut() {
+                let sp = superpose(conf.positions(), &positions_ref, &indices, &weights);
+                rmsd.push(sp.rmsd);
+                conf.set_positions(sp.apply(conf.positions()));
+            }
+            if reference != AlignReference::Average {
+                break;
+            }
+            let average = average_positions(self);
+            let shift = positions_ref.iter().zip(&average).map(|(p, q)| squared_distance(p, q)).fold(0.0, f64::max);
+            positions_ref = average;
+            if shift.sqrt() < AVERAGE_CONVERGENCE {
+                break;
+            }
+        }
+        // RMSD against the final average, not the one of previous pass
+        if reference == AlignReference::Average {
+            rmsd = self
+                .frames
+                .iter()
+                .map(|conf| superpose(conf.positions(), &positions_ref, &indices, &weights).rmsd)
+                .collect();
+        }
+
+        let average = average_positions(self);
+        let n = self.nframes() as f64;
+        let mut rmsf = vec![0.0; average.len()];
+        for frame in self.iter() {
+            for (f, (p, q)) in rmsf.iter_mut().zip(frame.positions().iter().zip(&average)) {
+                *f += squared_distance(p, q) / n;
+            }
+        }
+        rmsf.iter_mut().for_each(|f| *f = f.sqrt());
+        let mut mol_average = self.parent().clone();
+        mol_average.set_positions(average);
+        mol_average.set_title("average structure");
+
+        Ok(TrajectoryAlignment {
+            rmsd,
+            rmsf,
+            average: mol_average,
+        })
+    }
+}
+// 279f0f45 ends here
+
+// [[file:../gchemol-core.note::784aede9][784aede9]]
+#[test]
+fn test_superimpose_frames() {
+    use std::convert::TryFrom;
+    use vecfx::approx::assert_relative_eq;
+
+    // rigidly rotated and translated copies of a molecule, with one
+    // hydrogen atom vibrating along x
+    let mol = Molecule::from_database("CH4");
+    let mols: Vec<_> = (0..4)
+        .map(|i| {
+            let mut m = mol.clone();
+            let [x, y, z] = m.get_atom(2).unwrap().position();
+            let dx = if i % 2 == 0 { 0.1 } else { -0.1 };
+            m.set_position(2, [x + dx, y, z]);
+            let angle = i as f64 * 0.5;
+            let (s, c) = angle.sin_cos();
+            let positions: Vec<_> = m.positions().map(|[x, y, z]| [c * x - s * y + i as f64, s * x + c * y, z]).collect();
+            m.set_positions(positions);
+            m
+        })
+        .collect();
+    let mut traj = Trajectory::try_from(mols).unwrap();
+
+    // fitting on the rigid part only
+    let rigid = [1, 3, 4, 5];
+    let result = traj.superimpose_frames(AlignReference::Frame(0), Some(&rigid)).unwrap();
+    assert_eq!(result.rmsd.len(), 4);
+    assert!(result.rmsd.iter().all(|&x| x < 1e-6));
+    assert_relative_eq!(result.rmsf[0], 0.0, epsilon = 1e-6);
+    assert_relative_eq!(result.rmsf[1], 0.1, epsilon = 1e-6);
+    assert_eq!(result.average.natoms(), 5);
+    let p0 = traj[0].positions()[2];
+    let p1 = traj[1].positions()[2];
+    assert_relative_eq!(squared_distance(&p0, &p1), 0.0, epsilon = 1e-8);
+
+    // the average structure as reference
+    let result = traj.superimpose_frames(AlignReference::Average, None).unwrap();
+    assert!(result.rmsd.iter().all(|&x| x > 0.0));
+    assert!(result.rmsf[1] > result.rmsf[0]);
+    let average: Vec<_> = result.average.positions().collect();
+    let all: Vec<_> = (0..5).collect();
+    let weights: Vec<_> = traj.parent().masses().collect();
+    for (frame, &x) in traj.iter().zip(&result.rmsd) {
+        let sp = superpose(frame.positions(), &average, &all, &weights);
+        assert_relative_eq!(sp.rmsd, x, epsilon = 1e-8);
+    }
+    assert!(traj.superimpose_frames(AlignReference::Frame(4), None).is_err());
+    assert!(traj.superimpose_frames(AlignReference::Frame(0), Some(&[9])).is_err());
+}
+
+#[test]
+fn test_superimpose_frames_chirality() {
+    use crate::Atom;
+    use std::convert::TryFrom;
+
+    // CHFClBr and its enantiomer by mirroring through the yz plane
+    let mol = Molecule::from_atoms(vec![
+        Atom::new("C", [0.0, 0.0, 0.0]),
+        Atom::new("H", [0.0, 0.0, 1.09]),
+        Atom::new("F", [1.30, 0.0, -0.46]),
+        Atom::new("Cl", [-0.88, 1.53, -0.62]),
+        Atom::new("Br", [-0.96, -1.66, -0.68]),
+    ]);
+    let mut mirrored = mol.clone();
+    let positions: Vec<_> = mol.positions().map(|[x, y, z]| [-x, y, z]).collect();
+    mirrored.set_positions(positions);
+
+    // signed volume spanned by substituents around carbon
+    let chirality = |p: &[[f64; 3]]| {
+        let v = |i: usize| vecfx::Vector3f::from(p[i]) - vecfx::Vector3f::from(p[0]);
+        v(1).dot(&v(2).cross(&v(3))).signum()
+    };
+    let mut traj = Trajectory::try_from(vec![mol, mirrored]).unwrap();
+    let before: Vec<_> = traj.iter().map(|frame| chirality(frame.positions())).collect();
+    assert_ne!(before[0], before[1]);
+    for reference in [AlignReference::Frame(0), AlignReference::Average] {
+        let result = traj.superimpose_frames(reference, None).unwrap();
+        let after: Vec<_> = traj.iter().map(|frame| chirality(frame.positions())).collect();
+        assert_eq!(before, after);
+        // enantiomers cannot be superimposed by proper rotations
+        assert!(result.rmsd.iter().any(|&x| x > 0.1));
+    }
+}
+// 784aede9 ends here
@@ -0,0 +1,226 @@
+// [[file:../gchemol-core.note::753c3323][753c3323]]
+//! Kinetic energy, temperature and velocity utilities for `Molecule`
+// 753c3323 ends here
+
+// [[file:../gchemol-core.note::f52aabae][f52aabae]]
+use crate::common::*;
+use crate::inertia::AMU;
+use crate::Molecule;
+
+use rand::Rng;
+use vecfx::{Matrix3f, Vector3f};
+// f52aabae ends here
+
+// [[file:../gchemol-core.note::b9865d84][b9865d84]]
+/// Elementary charge in C, for conversion from J to eV
+const ELECTRON_VOLT: f64 = 1.602176634e-19;
+/// Boltzmann constant in eV/K
+const BOLTZMANN: f64 = 8.617333262e-5;
+/// Kinetic energy unit amu·Å²/fs² in eV
+const AMU_A2_FS2: f64 = AMU * 1e10 / ELECTRON_VOLT;
+
+/// Kinetic energy, temperature and velocities
+///
+/// Velocities are in Å/fs, masses in amu, energies in eV and
+/// temperatures in K.
+impl Molecule {
+    /// Return the number of degrees of freedom for temperature: three
+    /// for each atom, excluding frozen Cartesian coordinates. If no
+    /// coordinates are frozen, three more are excluded for conserved
+    /// center-of-mass translation of multiple atoms.
+    pub fn degrees_of_freedom(&self) -> usize {
+        let nfrozen = self.freezing_coords_mask().nmasked();
+        let nfree = 3 * self.natoms() - nfrozen;
+        if nfrozen == 0 && self.natoms() > 1 {
+            nfree - 3
+        } else {
+            nfree
+        }
+    }
+
+    /// Return the total kinetic energy of all atoms in eV.
+    pub fn kinetic_energy(&self) -> f64 {
+        let ek: f64 = self
+            .masses()
+            .zip(self.velocities())
+            .map(|(m, v)| 0.5 * m * Vector3f::from(v).norm_squared())
+            .sum();
+        ek * AMU_A2_FS2
+    }
+
+    /// Return the instantaneous temperature in K from kinetic energy and
+    /// [degrees_of_freedom](#method.degrees_of_freedom). Return zero if
+    /// there is no degree of freedom.
+    pub fn temperature(&self) -> f64 {
+        match self.degrees_of_freedom() {
+            0 => 0.0,
+            n => 2.0 * self.kinetic_energy() / (n as f64 * BOLTZMANN),
+        }
+    }
+
+    /// Set velocities of atoms in sequential order, keeping frozen
+    /// components at zero.
+    fn set_free_velocities(&mut self, velocities: Vec<Vector3f>) {
+        let frozen: Vec<_> = self.atoms().map(|(_, a)| a.freezing()).collect();
+        let velocities = velocities.into_iter().zip(frozen).map(|(mut v, mask)| {
+            (0..3).filter(|&k| mask[k]).for_each(|k| v[k] = 0.0);
+            v
+        });
+        self.set_velocities(velocities.collect_vec());
+    }
+
+    /// Remove center-of-mass translation from velocities.
+    ///
+    /// # NOTE
+    /// * Frozen velocity components are kept at zero, so the total
+    ///   momentum may not vanish exactly if any atom is frozen.
+    pub fn remove_com_translation(&mut self) {
+        let total: f64 = self.masses().sum();
+        if total <= 0.0 {
+            return;
+        }
+        let momentum: Vector3f = self.masses().zip(self.velocities()).map(|(m, v)| m * Vector3f::from(v)).sum();
+        let vcom = momentum / total;
+        let velocities = self.velocities().map(|v| Vector3f::from(v) - vcom).collect();
+        self.set_free_velocities(velocities);
+    }
+
+    /// Remove rigid rotation around the center of mass from velocities,
+    /// by subtracting the angular velocity from total angular momentum
+    /// and inertia tensor. Rotation around the axis of linear molecule is
+    /// ignored.
+    pub fn remove_com_rotation(&mut self) {
+        if self.masses().sum::<f64>() <= 0.0 {
+            return;
+        }
+        let com = Vector3f::from(self.center_of_mass());
+        let positions: Vec<_> = self.positions().map(|p| Vector3f::from(p) - com).collect();
+        let angular: Vector3f = positions
+            .iter()
+            .zip(self.masses().zip(self.velocities()))
+            .map(|(r, (m, v))| m * r.cross(&Vector3f::from(v)))
+            .sum();
+
+        // angular velocity using pseudo-inverse of inertia tensor
+        let eigen = Matrix3f::from_row_slice(&self.inertia_matrix().concat()).symmetric_eigen();
+        let max = eigen.eigenvalues.amax();
+        let mut omega = Vector3f::zeros();
+        for (k, &lambda) in eigen.eigenvalues.iter().enumerate() {
+            if lambda > 1e-8 * max {
+                let u = eigen.eigenvectors.column(k);
+                omega += u * (u.dot(&angular) / lambda);
+            }
+        }
+        let velocities = self
+            .velocities()
+            .zip(&positions)
+            .map(|(v, r)| Vector3f::from(v) - omega.cross(r))
+            .collect();
+        self.set_free_velocities(velocities);
+    }
+
+    /// Scale velocities to reach `temperature` in K. Return error if the
+    /// current temperature is zero.
+    pub fn rescale_velocities(&mut self, temperature: f64) -> Result<()> {
+        ensure!(temperature >= 0.0, "invalid temperature: {temperature}");
+        let current = self.temperature();
+        ensure!(current > 0.0, "cannot rescale velocities at zero temperature");
+        let factor = (temperature / current).sqrt();
+        let velocities = self.velocities().map(|v| Vector3f::from(v) * factor).collect_vec();
+        self.set_velocities(velocities);
+        Ok(())
+    }
+
+    /// Initialize velocities from Maxwell-Boltzmann distribution at
+    /// `temperature` in K, using random number generator `rng`, which
+    /// can be seeded for reproducibility. Return error if `temperature`
+    /// is negative.
+    ///
+    /// # NOTE
+    /// * Frozen components and atoms without mass get zero velocity.
+    /// * If no coordinates are frozen, center-of-mass translation is
+    ///   removed. Velocities are then rescaled to match `temperature`
+    ///   exactly.
+    pub fn set_maxwell_boltzmann_velocities<R: Rng + ?Sized>(&mut self, temperature: f64, rng: &mut R) -> Result<()> {
+        ensure!(temperature >= 0.0, "invalid temperature: {temperature}");
+        let masses: Vec<_> = self.masses().collect();
+        let velocities = masses
+            .iter()
+            .map(|&m| {
+                if m > 0.0 {
+                    let sigma = (BOLTZMANN * temperature / (m * AMU_A2_FS2)).sqrt();
+                    Vector3f::from([0; 3].map(|_| sigma * standard_normal(rng)))
+                } else {
+                    Vector3f::zeros()
+                }
+            })
+            .collect();
+        self.set_free_velocities(velocities);
+        if self.freezing_coords_mask().nmasked() == 0 {
+            self.remove_com_translation();
+        }
+        if self.temperature() > 0.0 {
+            self.rescale_velocities(temperature)?;
+        }
+        Ok(())
+    }
+}
+
+/// Draw a sample from standard normal distribution by Box-Muller
+/// transform.
+fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+// b9865d84 ends here
+
+// [[file:../gchemol-core.note::3d5d5516][3d5d5516]]
+#[test]
+fn test_thermo() {
+    use crate::Atom;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use vecfx::approx::assert_relative_eq;
+
+    // two argon atoms moving apart at 0.01 Å/fs
+    let mut mol = Molecule::from_atoms(vec![Atom::new("Ar", [0.0, 0.0, 0.0]), Atom::new("Ar", [3.0, 0.0, 0.0])]);
+    mol.set_velocities([[-0.01, 0.0, 0.0], [0.01, 0.0, 0.0]]);
+    let m = mol.masses().next().unwrap();
+    let ek = m * 0.01f64.powi(2) * AMU_A2_FS2;
+    assert_relative_eq!(mol.kinetic_energy(), ek, epsilon = 1e-12);
+    assert_eq!(mol.degrees_of_freedom(), 3);
+    assert_relative_eq!(mol.temperature(), 2.0 * ek / (3.0 * BOLTZMANN), epsilon = 1e-8);
+    mol.rescale_velocities(300.0).unwrap();
+    assert_relative_eq!(mol.temperature(), 300.0, epsilon = 1e-8);
+
+    // translation and rotation
+    mol.set_velocities([[0.01, 0.02, 0.0], [0.01, 0.0, 0.0]]);
+    mol.remove_com_translation();
+    assert_relative_eq!(mol.get_atom(1).unwrap().velocity()[0], 0.0, epsilon = 1e-12);
+    assert_relative_eq!(mol.get_atom(1).unwrap().velocity()[1], 0.01, epsilon = 1e-12);
+    mol.remove_com_rotation();
+    assert_relative_eq!(mol.kinetic_energy(), 0.0, epsilon = 1e-12);
+    assert!(mol.rescale_velocities(300.0).is_err());
+
+    // frozen atoms are excluded from degrees of freedom
+    let mut mol = Molecule::from_database("CH4");
+    mol.get_atom_mut(1).unwrap().set_freezing([true; 3]);
+    assert_eq!(mol.degrees_of_freedom(), 12);
+    let mut rng = StdRng::seed_from_u64(42);
+    mol.set_maxwell_boltzmann_velocities(500.0, &mut rng).unwrap();
+    assert_relative_eq!(mol.temperature(), 500.0, epsilon = 1e-8);
+    assert_eq!(mol.get_atom(1).unwrap().velocity(), [0.0; 3]);
+    let velocities: Vec<_> = mol.velocities().collect();
+    mol.set_maxwell_boltzmann_velocities(500.0, &mut StdRng::seed_from_u64(42)).unwrap();
+    assert_eq!(mol.velocities().collect_vec(), velocities);
+
+    // no net translation without frozen atoms
+    mol.get_atom_mut(1).unwrap().set_freezing([false; 3]);
+    mol.set_maxwell_boltzmann_velocities(300.0, &mut rng).unwrap();
+    let momentum: Vector3f = mol.masses().zip(mol.velocities()).map(|(m, v)| m * Vector3f::from(v)).sum();
+    assert_relative_eq!(momentum.norm(), 0.0, epsilon = 1e-10);
+    assert_relative_eq!(mol.temperature(), 300.0, epsilon = 1e-8);
+    assert!(mol.set_maxwell_boltzmann_velocities(-1.0, &mut rng).is_err());
+}
+// 3d5d5516 ends here
@@ -5,7 +5,7 @@ use crate::PropertyStore;
 // ff231cb5 ends here
 
 // [[file:../gchemol-core.note::82f7facb][82f7facb]]
-use crate::Atom;
+use crate::{Atom, Point3, Vector3f};
 use std::collections::HashSet;
 
 /// A defined linked collection of atoms or a single atom within a
@@ -21,20 +21,136 @@ pub struct AtomGroup {
 }
 
 impl AtomGroup {
-    fn new(mol: &Molecule, atoms: &[usize]) -> Option<Self> {
-        let atoms = atoms.iter().filter_map(|&n| mol.get_node_index(n).copied()).collect();
+    /// Create a group of atoms `atoms` in `mol`. Return None if any serial
+    /// number is invalid.
+    ///
+    /// # NOTE
+    /// * The group is bound to `mol`: atoms are tracked by their
+    ///   identities in `mol`, not by serial numbers, so the group stays
+    ///   valid after renumbering.
+    pub fn new(mol: &Molecule, atoms: &[usize]) -> Option<Self> {
+        let atoms = atoms.iter().map(|&n| mol.get_node_index(n).copied()).collect::<Option<_>>()?;
         Self { atoms, ..Default::default() }.into()
     }
 
-    fn get_atoms<'a>(&'a self, mol: &'a Molecule) -> impl Iterator<Item = (usize, &'a Atom)> + 'a {
-        self.atoms.iter().map(move |&i| (mol.atom_sn(i), &mol.graph[i]))
+    /// Create a group of all atoms of element `symbol` in `mol`.
+    pub fn from_element(mol: &Molecule, symbol: &str) -> Self {
+        let atoms: Vec<_> = mol.atoms().filter(|(_, a)| a.symbol() == symbol).map(|(i, _)| i).collect();
+        Self::new(mol, &atoms).unwrap()
+    }
+
+    /// Create a group of all atoms in `mol`.
+    pub fn from_molecule(mol: &Molecule) -> Self {
+        let atoms: Vec<_> = mol.numbers().collect();
+        Self::new(mol, &atoms).unwrap()
+    }
+
+    /// Return the number of atoms in group.
+    pub fn len(&self) -> usize {
+        self.atoms.len()
+    }
+
+    /// Return true if group has no atoms.
+    pub fn is_empty(&self) -> bool {
+        self.atoms.is_empty()
+    }
+
+    /// Return true if atom `sn` in `mol` belongs to this group.
+    pub fn contains(&self, mol: &Molecule, sn: usize) -> bool {
+        mol.get_node_index(sn).is_some_and(|n| self.atoms.contains(n))
+    }
+
+    /// Add atom `sn` in `mol` into group. Return false if `sn` is invalid
+    /// or already in group.
+    pub fn insert(&mut self, mol: &Molecule, sn: usize) -> bool {
+        mol.get_node_index(sn).is_some_and(|&n| self.atoms.insert(n))
+    }
+
+    /// Remove atom `sn` in `mol` from group. Return false if `sn` is not
+    /// in group.
+    pub fn remove(&mut self, mol: &Molecule, sn: usize) -> bool {
+        mol.get_node_index(sn).is_some_and(|n| self.atoms.remove(n))
+    }
+
+    /// Remove all atoms from group.
+    pub fn clear(&mut self) {
+        self.atoms.clear();
+    }
+
+    /// Drop atom `n` removed from parent molecule.
+    pub(crate) fn discard(&mut self, n: NodeIndex) {
+        self.atoms.remove(&n);
+    }
+
+    /// Iterate over serial numbers of atoms in group in ascending order.
+    pub fn serial_numbers<'a>(&'a self, mol: &'a Molecule) -> impl Iterator<Item = usize> + 'a {
+        self.atoms.iter().filter_map(|n| mol.mapping.get_by_right(n).copied()).sorted()
+    }
+
+    /// Iterate over atoms in group ordered by serial numbers.
+    pub fn atoms<'a>(&'a self, mol: &'a Molecule) -> impl Iterator<Item = (usize, &'a Atom)> + 'a {
+        self.serial_numbers(mol).map(move |i| (i, mol.get_atom_unchecked(i)))
+    }
+
+    /// Return a new group with atoms in `self` or `other`. Properties are
+    /// not kept.
+    pub fn union(&self, other: &AtomGroup) -> AtomGroup {
+        let atoms = self.atoms.union(&other.atoms).copied().collect();
+        Self { atoms, ..Default::default() }
+    }
+
+    /// Return a new group with atoms in both `self` and `other`.
+    /// Properties are not kept.
+    pub fn intersection(&self, other: &AtomGroup) -> AtomGroup {
+        let atoms = self.atoms.intersection(&other.atoms).copied().collect();
+        Self { atoms, ..Default::default() }
+    }
+
+    /// Return a new group with atoms in `self` but not in `other`.
+    /// Properties are not kept.
+    pub fn difference(&self, other: &AtomGroup) -> AtomGroup {
+        let atoms = self.atoms.difference(&other.atoms).copied().collect();
+        Self { atoms, ..Default::default() }
+    }
+
+    /// Return the center of mass of atoms in group. Return None if group
+    /// is empty.
+    pub fn center_of_mass(&self, mol: &Molecule) -> Option<Point3> {
+        use gchemol_geometry::prelude::*;
+
+        if self.is_empty() {
+            return None;
+        }
+        let (positions, masses): (Vec<_>, Vec<_>) = self
+            .atoms(mol)
+            .map(|(_, a)| (a.position(), a.get_mass().unwrap_or_default()))
+            .unzip();
+        positions.center_of_mass(&masses).into()
+    }
+
+    /// Return the chemical formula of atoms in group.
+    pub fn formula(&self, mol: &Molecule) -> String {
+        crate::formula::get_reduced_formula(self.atoms(mol).map(|(_, a)| a.symbol()))
+    }
+
+    /// Translate atoms of this group in `mol` by displacement `disp`.
+    pub fn translate<P: Into<Vector3f>>(&self, mol: &mut Molecule, disp: P) {
+        let disp: Vector3f = disp.into();
+        for n in self.atoms.iter() {
+            if let Some(atom) = mol.graph.raw_graph_mut().node_weight_mut(*n) {
+                let p: Vector3f = atom.position().into();
+                atom.set_position(p + disp);
+            }
+        }
     }
 }
 
 /// Atom groups related methods
 impl Molecule {
     /// Define a new atom group with `group_name` using atoms in
-    /// `group`. Old group with the same name will be overwrote.
+    /// `group`. Old group with the same name will be overwrote and
+    /// returned. Return None without defining the group if any atom in
+    /// `group` is invalid.
     pub fn define_group(&mut self, group_name: &str, group: &[usize]) -> Option<AtomGroup> {
         let group = AtomGroup::new(self, group)?;
         self.groups.insert(group_name.to_string(), group)
@@ -72,7 +188,16 @@ impl Molecule {
     /// Gets atoms in group `group_name`.
     pub fn get_atoms_in_group(&self, group_name: &str) -> Option<impl Iterator<Item = (usize, &Atom)>> {
         let group = self.get_group(group_name)?;
-        Some(group.get_atoms(self))
+        Some(group.atoms(self))
+    }
+
+    /// Translate atoms in group `group_name` by displacement `disp`.
+    /// Return None if there is no such group.
+    pub fn translate_group<P: Into<Vector3f>>(&mut self, group_name: &str, disp: P) -> Option<()> {
+        let group = self.groups.remove(group_name)?;
+        group.translate(self, disp);
+        self.groups.insert(group_name.to_string(), group);
+        Some(())
     }
 }
 // 82f7facb ends here
@@ -84,12 +209,31 @@ fn create_submolecule_from_atoms<'a>(mol: &Molecule, atoms: impl IntoIterator<It
     let nodes = nodes?;
     let graph = mol.graph().subgraph(&nodes);
 
-    Molecule::from_graph_raw(graph, atoms).into()
+    let mut sub = Molecule::from_graph_raw(graph, atoms);
+    inherit_groups(&mut sub, mol);
+    sub.into()
+}
+
+/// Copy atom groups of `parent` into `mol` sharing the same numbering
+/// system. Groups with no atoms in `mol` are not kept.
+fn inherit_groups(mol: &mut Molecule, parent: &Molecule) {
+    for (name, group) in parent.groups.iter() {
+        let atoms = group.serial_numbers(parent).filter_map(|i| mol.get_node_index(i).copied()).collect();
+        let group = AtomGroup {
+            atoms,
+            properties: group.properties.clone(),
+        };
+        if !group.is_empty() {
+            mol.groups.insert(name.to_owned(), group);
+        }
+    }
 }
 // 51a9048d ends here
 
 // [[file:../gchemol-core.note::687744ec][687744ec]]
+use crate::Bond;
 use gchemol_graph::petgraph::algo;
+use gchemol_graph::petgraph::graph::UnGraph;
 
 /// High level topology structure of `Molecule`.
 impl Molecule {
@@ -129,6 +273,20 @@ impl Molecule {
         }
     }
 
+    /// Return a compact graph referencing atoms and bonds, together with
+    /// the atom serial number of each node. Nodes are ordered by serial
+    /// numbers.
+    pub(crate) fn compact_graph(&self) -> (UnGraph<&Atom, &Bond>, Vec<usize>) {
+        let numbers: Vec<_> = self.numbers().collect();
+        let mut graph = UnGraph::with_capacity(numbers.len(), self.nbonds());
+        let node_map: std::collections::HashMap<_, _> = self.atoms().map(|(sn, atom)| (sn, graph.add_node(atom))).collect();
+        for (i, j, bond) in self.bonds() {
+            graph.add_edge(node_map[&i], node_map[&j], bond);
+        }
+
+        (graph, numbers)
+    }
+
     /// Return all directly bonded atoms with `a`
     pub fn connected(&self, a: usize) -> impl Iterator<Item = usize> + '_ {
         let node = self.node_index(a);
@@ -141,6 +299,8 @@ impl Molecule {
     ///
     /// # NOTE
     /// * The sub molecule shares the same numbering system with its parent.
+    /// * Atom groups (including residues) are kept for atoms in the sub
+    ///   molecule.
     pub fn get_sub_molecule<'a>(&self, atoms: impl IntoIterator<Item = &'a usize>) -> Option<Molecule> {
         create_submolecule_from_atoms(&self, atoms)
     }
@@ -165,12 +325,15 @@ impl Molecule {
     /// Break molecule into multiple fragments based on its bonding
     /// connectivity. Return molecules whole connected by bonds
     /// without periodic lattice. The atom numbers in fragments will
-    /// be the same as in their parent.
+    /// be the same as in their parent. Atom groups are kept for atoms
+    /// in each fragment.
     pub fn fragmented(&self) -> impl Iterator<Item = Self> + '_ {
         self.graph().connected_components_node_indices().map(|nodes| {
             let numbers: Vec<_> = nodes.iter().map(|&n| self.atom_sn(n)).collect();
             let g = self.graph().subgraph(&nodes);
-            Molecule::from_graph_raw(g, numbers)
+            let mut frag = Molecule::from_graph_raw(g, numbers);
+            inherit_groups(&mut frag, self);
+            frag
         })
     }
 
@@ -244,3 +407,46 @@ fn test_topo_path() {
     assert_eq!(numbers.len(), mol2.natoms());
 }
 // cf82e7a7 ends here
+
+// [[file:../gchemol-core.note::6cd5c2cf][6cd5c2cf]]
+#[test]
+fn test_atom_group() {
+    let mut mol = Molecule::from_database("CH4");
+    assert!(AtomGroup::new(&mol, &[1, 9]).is_none());
+    assert!(mol.define_group("bad", &[1, 9]).is_none());
+    assert!(!mol.has_group("bad"));
+
+    let hydrogens = AtomGroup::from_element(&mol, "H");
+    assert_eq!(hydrogens.len(), 4);
+    assert_eq!(hydrogens.formula(&mol), "H4");
+    let ch = AtomGroup::new(&mol, &[1, 2]).unwrap();
+    assert_eq!(ch.union(&hydrogens).len(), 5);
+    assert_eq!(ch.intersection(&hydrogens).serial_numbers(&mol).collect_vec(), vec![2]);
+    assert_eq!(hydrogens.difference(&ch).serial_numbers(&mol).collect_vec(), vec![3, 4, 5]);
+    assert!(AtomGroup::from_molecule(&mol).contains(&mol, 5));
+
+    let mut group = ch.clone();
+    assert!(group.insert(&mol, 3));
+    assert!(!group.insert(&mol, 3));
+    assert!(group.remove(&mol, 3));
+    assert!(!group.contains(&mol, 3));
+
+    // translate group only
+    mol.define_group("ch", &[1, 2]);
+    let com = mol.get_group("ch").unwrap().center_of_mass(&mol).unwrap();
+    mol.translate_group("ch", [1.0, 0.0, 0.0]).unwrap();
+    let com_new = mol.get_group("ch").unwrap().center_of_mass(&mol).unwrap();
+    assert!((com_new[0] - com[0] - 1.0).abs() < 1e-8);
+    assert_eq!(mol.get_atom(3).unwrap().position(), Molecule::from_database("CH4").get_atom(3).unwrap().position());
+
+    // groups follow renumbering and are pruned on atom removal
+    mol.remove_atom(1);
+    assert_eq!(mol.get_atoms_in_group("ch").unwrap().map(|(i, _)| i).collect_vec(), vec![2]);
+    mol.renumber();
+    assert_eq!(mol.get_atoms_in_group("ch").unwrap().map(|(i, _)| i).collect_vec(), vec![1]);
+    // a new atom taking the vacant slot does not join the group
+    mol.add_atom(9, crate::Atom::new("O", [0.0; 3]));
+    assert_eq!(mol.get_group("ch").unwrap().len(), 1);
+    assert!(mol.get_group("ch").unwrap().center_of_mass(&mol).is_some());
+}
+// 6cd5c2cf ends here
@@ -10,6 +10,7 @@ use crate::Bond;
 use crate::PropertyStore;
 
 use crate::common::*;
+use std::borrow::Cow;
 // a6e9e016 ends here
 
 // [[file:../gchemol-core.note::fabd768f][fabd768f]]
@@ -33,17 +34,51 @@ mod configuration {
 
         /// Configuration associated properties
         pub properties: PropertyStore,
+
+        /// Atom velocities.
+        #[serde(default)]
+        pub(super) velocities: Option<Vec<[f64; 3]>>,
+
+        /// Forces on atoms.
+        #[serde(default)]
+        pub(super) forces: Option<Vec<[f64; 3]>>,
+
+        /// Potential energy of this frame.
+        #[serde(default)]
+        pub energy: Option<f64>,
+
+        /// Simulation step of this frame.
+        #[serde(default)]
+        pub step: Option<usize>,
+
+        /// Simulation time of this frame.
+        #[serde(default)]
+        pub time: Option<f64>,
     }
 
     impl Configuration {
-        /// Construct `Configuration` from `mol`
+        /// Construct `Configuration` from `mol`. Velocities are kept if
+        /// any atom is moving.
         pub(crate) fn from_molecule(mol: &Molecule) -> Self {
+            let velocities: Vec<_> = mol.velocities().collect();
+            let moving = velocities.iter().flatten().any(|&v| v != 0.0);
             Self {
                 title: mol.title().to_owned(),
                 positions: mol.positions().collect(),
                 lattice: mol.lattice.clone(),
                 properties: mol.properties.clone(),
                 bonds: mol.bonds().map(|(u, v, b)| (u, v, b.clone())).collect(),
+                velocities: moving.then_some(velocities),
+                ..Default::default()
+            }
+        }
+
+        /// Construct `Configuration` with `positions` and `lattice` only.
+        pub(crate) fn from_positions(positions: Vec<[f64; 3]>, lattice: Option<Lattice>) -> Self {
+            Self {
+                positions,
+                lattice,
+                ..Default::default()
             }
         }
 
@@ -52,15 +87,68 @@ mod configuration {
             mol.lattice = self.lattice.clone();
             mol.set_positions(self.positions.clone());
             mol.properties = self.properties.clone();
+            mol.set_title(&self.title);
+            match &self.velocities {
+                Some(velocities) => mol.set_velocities(velocities.iter().copied()),
+                None => mol.set_velocities(std::iter::repeat([0.0; 3])),
+            }
             mol
         }
+
+        /// Return atom positions in this frame.
+        pub fn positions(&self) -> &[[f64; 3]] {
+            &self.positions
+        }
+
+        /// Return the lattice in this frame.
+        pub fn lattice(&self) -> Option<&Lattice> {
+            self.lattice.as_ref()
+        }
+
+        /// Return bonds in this frame as `(sn1, sn2, bond)`.
+        pub fn bonds(&self) -> &[(usize, usize, Bond)] {
+            &self.bonds
+        }
+
+        /// Return atom velocities in this frame if available.
+        pub fn velocities(&self) -> Option<&[[f64; 3]]> {
+            self.velocities.as_deref()
+        }
+
+        /// Set atom velocities in this frame.
+        ///
+        /// # Panics
+        ///
+        /// * panics if the number of velocities differs from the number of atoms.
+        pub fn set_velocities(&mut self, velocities: Vec<[f64; 3]>) {
+            assert_eq!(velocities.len(), self.positions.len(), "invalid number of velocities");
+            self.velocities = Some(velocities);
+        }
+
+        /// Return forces on atoms in this frame if available.
+        pub fn forces(&self) -> Option<&[[f64; 3]]> {
+            self.forces.as_deref()
+        }
+
+        /// Set forces on atoms in this frame.
+        ///
+        /// # Panics
+        ///
+        /// * panics if the number of forces differs from the number of atoms.
+        pub fn set_forces(&mut self, forces: Vec<[f64; 3]>) {
+            assert_eq!(forces.len(), self.positions.len(), "invalid number of forces");
+            self.forces = Some(forces);
+        }
     }
 
     impl Configuration {
         /// Update `parent` with current image state.
         pub(crate) fn update_molecule(&self, parent: &mut Molecule) {
             parent.set_positions(self.positions.to_owned());
-            // parent.set_velocities(self.velocities.to_owned());
+            match &self.velocities {
+                Some(velocities) => parent.set_velocities(velocities.iter().copied()),
+                None => parent.set_velocities(std::iter::repeat([0.0; 3])),
+            }
             parent.lattice = self.lattice.clone();
             parent.set_title(self.title.to_owned());
         }
@@ -110,15 +198,10 @@ fn matching(mol1: &Molecule, mol2: &Molecule) -> bool {
 
 // [[file:../gchemol-core.note::c0387851][c0387851]]
 impl Trajectory {
-    /// Construct `Trajectory` object from a list of `Molecule`
-    fn new(mols: Vec<Molecule>) -> Self {
-        // FIXME: avoid re-allocation
-        let frames: Vec<_> = mols.iter().map(Configuration::from_molecule).collect();
-
-        Self {
-            frames,
-            parent: mols[0].clone(),
-        }
+    /// Construct an empty `Trajectory` with `parent` molecule providing
+    /// atoms and other data shared by all frames.
+    pub fn new(parent: Molecule) -> Self {
+        Self { parent, frames: vec![] }
     }
 
     /// Return true if trajectory has no frames.
@@ -136,13 +219,133 @@ impl Trajectory {
         self.parent.natoms()
     }
 
-    /// Return an iterator over each `Molecule` in trajectory
-    fn iter(&self) -> impl Iterator<Item = Molecule> + '_ {
-        self.frames.iter().map(|conf| conf.to_molecule(&self.parent))
+    /// Return the parent molecule shared by all frames.
+    pub fn parent(&self) -> &Molecule {
+        &self.parent
+    }
+
+    /// Return an iterator over frames in trajectory. The parent
+    /// molecule is borrowed, not cloned.
+    pub fn iter(&self) -> impl Iterator<Item = Frame<'_>> + '_ {
+        self.frames.iter().map(|conf| Frame {
+            parent: &self.parent,
+            conf: Cow::Borrowed(conf),
+        })
+    }
+
+    /// Return frame `i`. Return None if out of range.
+    pub fn get(&self, i: usize) -> Option<Frame<'_>> {
+        let conf = self.frames.get(i)?;
+        Some(Frame {
+            parent: &self.parent,
+            conf: Cow::Borrowed(conf),
+        })
+    }
+
+    /// Return a new trajectory with frames in `range`.
+    ///
+    /// # Panics
+    ///
+    /// * panics if `range` is out of bounds.
+    pub fn slice<R: std::ops::RangeBounds<usize>>(&self, range: R) -> Self {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        Self {
+            parent: self.parent.clone(),
+            frames: self.frames[range].to_vec(),
+        }
+    }
+
+    /// Return a new trajectory with every `step`-th frame, starting from
+    /// the first.
+    pub fn stride(&self, step: usize) -> Self {
+        assert!(step > 0, "invalid step: {step}");
+        Self {
+            parent: self.parent.clone(),
+            frames: self.frames.iter().step_by(step).cloned().collect(),
+        }
+    }
+
+    /// Append `mol` as a new frame. Return error if `mol` does not match
+    /// atoms and periodicity of the parent molecule. If the trajectory
+    /// has no atoms, `mol` will be taken as the parent.
+    pub fn push(&mut self, mol: &Molecule) -> Result<()> {
+        if self.frames.is_empty() && self.parent.natoms() == 0 {
+            self.parent = mol.clone();
+        }
+        ensure!(matching(&self.parent, mol), "molecule does not match trajectory frames");
+        self.frames.push(Configuration::from_molecule(mol));
+        Ok(())
+    }
+
+    /// Append all molecules in `mols` as new frames. Return error if any
+    /// molecule does not match, in which case no frame will be added.
+    pub fn extend<I: IntoIterator<Item = Molecule>>(&mut self, mols: I) -> Result<()> {
+        let mols: Vec<_> = mols.into_iter().collect();
+        let parent = if self.frames.is_empty() && self.parent.natoms() == 0 {
+            mols.first().unwrap_or(&self.parent)
+        } else {
+            &self.parent
+        };
+        if let Some(i) = mols.iter().position(|mol| !matching(parent, mol)) {
+            bail!("molecule {i} does not match trajectory frames");
+        }
+        for mol in mols.iter() {
+            self.push(mol)?;
+        }
+        Ok(())
+    }
+
+    /// Remove and return frame `i`. Return None if out of range.
+    pub fn remove_frame(&mut self, i: usize) -> Option<Configuration> {
+        (i < self.frames.len()).then(|| self.frames.remove(i))
+    }
+}
+
+impl std::ops::Index<usize> for Trajectory {
+    type Output = Configuration;
+
+    fn index(&self, i: usize) -> &Configuration {
+        &self.frames[i]
     }
 }
 // c0387851 ends here
 
+// [[file:../gchemol-core.note::affec4f1][affec4f1]]
+/// Read access to frames shared by in-memory `Trajectory` and
+/// disk-backed `FrameStore`, for code working with either of them.
+pub trait Frames {
+    /// Return the number of frames.
+    fn nframes(&self) -> usize;
+
+    /// Return the parent molecule shared by all frames.
+    fn parent(&self) -> &Molecule;
+
+    /// Return frame `i`. Return None if out of range.
+    fn get(&self, i: usize) -> Option<Frame<'_>>;
+
+    /// Return an iterator over frames.
+    fn frames(&self) -> impl Iterator<Item = Frame<'_>> + '_;
+}
+
+impl Frames for Trajectory {
+    fn nframes(&self) -> usize {
+        self.nframes()
+    }
+
+    fn parent(&self) -> &Molecule {
+        self.parent()
+    }
+
+    fn get(&self, i: usize) -> Option<Frame<'_>> {
+        self.get(i)
+    }
+
+    fn frames(&self) -> impl Iterator<Item = Frame<'_>> + '_ {
+        self.iter()
+    }
+}
+// affec4f1 ends here
+
 // [[file:../gchemol-core.note::0a527b59][0a527b59]]
 use std::convert::TryFrom;
 
@@ -150,16 +353,74 @@ impl TryFrom<Vec<Molecule>> for Trajectory {
     type Error = Error;
 
     fn try_from(mols: Vec<Molecule>) -> Result<Self> {
+        ensure!(!mols.is_empty(), "no molecules for trajectory!");
         for (i, pair) in mols.windows(2).enumerate() {
             if !matching(&pair[0], &pair[1]) {
                 bail!("found inconsistent molecules: {} -- {}!", i, i + 1)
             }
         }
-        Ok(Self::new(mols))
+        let frames = mols.iter().map(Configuration::from_molecule).collect();
+        let parent = mols.into_iter().next().unwrap();
+        Ok(Self { parent, frames })
+    }
+}
+
+impl From<Trajectory> for Vec<Molecule> {
+    fn from(traj: Trajectory) -> Self {
+        traj.iter().map(|frame| frame.to_molecule()).collect()
     }
 }
 // 0a527b59 ends here
 
+// [[file:../gchemol-core.note::977dc764][977dc764]]
+/// A view of a frame in `Trajectory` or `FrameStore`, which gives access
+/// to the configuration and the shared parent molecule. The configuration
+/// is borrowed from in-memory trajectory, or owned if loaded from disk.
+#[derive(Debug, Clone)]
+pub struct Frame<'a> {
+    parent: &'a Molecule,
+    conf: Cow<'a, Configuration>,
+}
+
+impl<'a> Frame<'a> {
+    /// Construct a frame owning `conf` loaded from storage.
+    pub(crate) fn from_owned(parent: &'a Molecule, conf: Configuration) -> Self {
+        Self {
+            parent,
+            conf: Cow::Owned(conf),
+        }
+    }
+
+    /// Return the parent molecule shared by all frames.
+    pub fn parent(&self) -> &'a Molecule {
+        self.parent
+    }
+
+    /// Return the configuration of this frame.
+    pub fn configuration(&self) -> &Configuration {
+        &self.conf
+    }
+
+    /// Return the configuration of this frame, cloning it if borrowed.
+    pub fn into_configuration(self) -> Configuration {
+        self.conf.into_owned()
+    }
+
+    /// Return a new `Molecule` in the state of this frame.
+    pub fn to_molecule(&self) -> Molecule {
+        self.conf.to_molecule(self.parent)
+    }
+}
+
+impl std::ops::Deref for Frame<'_> {
+    type Target = Configuration;
+
+    fn deref(&self) -> &Configuration {
+        &self.conf
+    }
+}
+// 977dc764 ends here
+
 // [[file:../gchemol-core.note::205c130c][205c130c]]
 /// Trajectory related methods
 impl Molecule {
@@ -188,3 +449,72 @@ fn test_trajectory() {
     assert!(!mol2.matching_configuration(&mol1));
 }
 // 342ffb64 ends here
+
+// [[file:../gchemol-core.note::ae1fdebd][ae1fdebd]]
+#[test]
+fn test_trajectory_frames() {
+    use std::convert::TryInto;
+
+    let mol = Molecule::from_database("CH4");
+    let mols: Vec<_> = (0..5)
+        .map(|i| {
+            let mut m = mol.clone();
+            m.translate([i as f64, 0.0, 0.0]);
+            m.set_title(&format!("frame {i}"));
+            m
+        })
+        .collect();
+    let mut traj: Trajectory = mols.clone().try_into().unwrap();
+    assert_eq!(traj.nframes(), 5);
+    assert_eq!(traj.natoms(), 5);
+    assert_eq!(traj.iter().count(), 5);
+    assert_eq!(traj[2].title, "frame 2");
+    assert_eq!(traj.get(3).unwrap().positions()[0], mols[3].positions().next().unwrap());
+    assert!(traj.get(5).is_none());
+    assert_eq!(traj.get(1).unwrap().to_molecule().title(), "frame 1");
+
+    let sliced = traj.slice(1..3);
+    assert_eq!(sliced.iter().map(|f| f.title.clone()).collect_vec(), vec!["frame 1", "frame 2"]);
+    let strided = traj.stride(2);
+    assert_eq!(strided.iter().map(|f| f.title.clone()).collect_vec(), vec!["frame 0", "frame 2", "frame 4"]);
+
+    // push and extend are validated
+    let mut bad = mol.clone();
+    bad.get_atom_mut(2).unwrap().set_symbol("F");
+    assert!(traj.push(&bad).is_err());
+    assert!(traj.extend(vec![mol.clone(), bad]).is_err());
+    assert_eq!(traj.nframes(), 5);
+    traj.push(&mol).unwrap();
+    traj.extend(vec![mol.clone(), mol.clone()]).unwrap();
+    assert_eq!(traj.nframes(), 8);
+
+    assert_eq!(traj.remove_frame(0).unwrap().title, "frame 0");
+    assert!(traj.remove_frame(10).is_none());
+    let mols: Vec<Molecule> = traj.into();
+    assert_eq!(mols.len(), 7);
+    assert_eq!(mols[0].title(), "frame 1");
+
+    // build from scratch
+    let mut traj = Trajectory::default();
+    traj.push(&mol).unwrap();
+    assert_eq!(traj.natoms(), 5);
+    assert!(Trajectory::try_from(vec![]).is_err());
+
+    // MD data: velocities are carried through molecules
+    assert!(traj[0].velocities().is_none());
+    let mut moving = mol.clone();
+    moving.set_velocities(vec![[0.1, 0.0, 0.0]; 5]);
+    traj.push(&moving).unwrap();
+    traj.push(&mol).unwrap();
+    assert_eq!(traj[1].velocities().unwrap()[0], [0.1, 0.0, 0.0]);
+    let conf = &mut traj.frames[2];
+    conf.set_forces(vec![[0.0, 0.0, -1.0]; 5]);
+    conf.energy = Some(-1.5);
+    conf.step = Some(2);
+    conf.time = Some(1.0);
+    assert_eq!(traj[2].forces().unwrap()[4], [0.0, 0.0, -1.0]);
+    let mols: Vec<Molecule> = traj.into();
+    assert_eq!(mols[1].velocities().next().unwrap(), [0.1, 0.0, 0.0]);
+    assert_eq!(mols[2].velocities().next().unwrap(), [0.0; 3]);
+}
+// ae1fdebd ends here